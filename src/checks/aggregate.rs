@@ -0,0 +1,161 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::any::Any;
+
+use crate::checks::{PlainColumnCheck, ValuesMap, split_column_key};
+use crate::lookup::{AggregateFunction, LookupStore};
+
+lazy_static! {
+    static ref AGG_RE: Regex = Regex::new(
+        r#"\b(kept_)?agg\(\s*['"]([^'"]+)['"]\s*,\s*['"]([^'"]+)['"]\s*(?:,\s*['"]([^'"]+)['"]\s*)?\)"#
+    ).unwrap();
+}
+
+// rows are matched to the aggregated groups through this column unless another one is given
+const DEFAULT_AGGREGATE_COLUMN: &str = "id";
+
+// `agg('orders.customer_id', 'count')` in a CEL filter, optionally with the local column
+// the groups are matched by as a third argument; a pre-pass computes the groups over every
+// row of the grouped table, whatever its own filters keep. `kept_agg` takes the same
+// arguments and only counts the rows the grouped table keeps, so the filtering table is
+// processed after it.
+#[derive(Debug, Clone)]
+pub struct AggregateReference {
+    pub key: String,
+    pub spec: String,
+    pub column: String,
+    pub kept: bool,
+}
+
+impl AggregateReference {
+    pub fn find_all(definition: &str) -> Result<Vec<Self>, anyhow::Error> {
+        AGG_RE.captures_iter(definition).map(|captures| {
+            let reference = AggregateReference {
+                key: captures[2].to_owned(),
+                spec: captures[3].to_owned(),
+                column: captures.get(4).map_or(DEFAULT_AGGREGATE_COLUMN, |c| c.as_str()).to_owned(),
+                kept: captures.get(1).is_some(),
+            };
+            split_column_key(&reference.key)?;
+            AggregateFunction::parse(&reference.spec)?;
+            Ok(reference)
+        }).collect()
+    }
+
+    // identifies a call of `agg` or `kept_agg` by its literal arguments
+    pub fn call_key(kept: bool, key: &str, spec: &str, column: Option<&str>) -> String {
+        let function = if kept { "kept_agg " } else { "agg " };
+        String::from(function) + key + " " + spec + " " + column.unwrap_or(DEFAULT_AGGREGATE_COLUMN)
+    }
+
+    pub fn store_key(&self) -> String {
+        let store_key = String::from(&self.key) + ":" + &self.spec;
+        if self.kept { store_key + ":kept" } else { store_key }
+    }
+}
+
+#[derive(Debug)]
+pub struct PlainAggregateTest {
+    key: String,
+    table_name: String,
+    column_name: String,
+    column_key: String,
+    store_key: String,
+    function: AggregateFunction,
+    operand_column: Option<String>,
+}
+
+impl PlainAggregateTest {
+    pub fn from_reference(reference: &AggregateReference) -> Result<Self, anyhow::Error> {
+        let (table, column) = split_column_key(&reference.key)?;
+        let (function, operand_column) = AggregateFunction::parse(&reference.spec)?;
+        let store_key = reference.store_key();
+
+        Ok(PlainAggregateTest {
+            key: String::from("aggregate: ") + table + ": " + &store_key,
+            table_name: table.to_owned(),
+            column_name: column.to_owned(),
+            column_key: reference.key.to_owned(),
+            store_key,
+            function,
+            operand_column: operand_column.map(|c| c.to_owned()),
+        })
+    }
+}
+
+impl PlainColumnCheck for PlainAggregateTest {
+    fn new(definition: &str, table: &str) -> Result<impl PlainColumnCheck + 'static, anyhow::Error> where Self: Sized {
+        let Some(reference) = AggregateReference::find_all(definition)?.into_iter().next() else {
+            return Err(anyhow::anyhow!("cannot parse aggregate {definition}"));
+        };
+        let aggregate = PlainAggregateTest::from_reference(&reference)?;
+        if aggregate.table_name != table {
+            return Err(anyhow::anyhow!("table name mismatch"));
+        }
+        Ok(aggregate)
+    }
+
+    fn test_value(
+        &self,
+        value: &str,
+        data_type: &sqlparser::ast::DataType,
+        lookup_table: &mut LookupStore,
+    ) -> Result<bool, anyhow::Error> {
+        if self.operand_column.is_some() {
+            return Err(anyhow::anyhow!("{} needs the whole row to be aggregated", self.key));
+        }
+        if value != "NULL" {
+            lookup_table.accumulate(&self.store_key, self.function, value, data_type, None)?;
+        }
+        Ok(true)
+    }
+
+    fn test_row(
+        &self,
+        value_per_field: &ValuesMap,
+        lookup_table: &mut LookupStore,
+    ) -> Result<bool, anyhow::Error> {
+        let (str_value, data_type) = &value_per_field[&self.column_name];
+        if str_value == "NULL" {
+            return Ok(true);
+        }
+        let operand = match &self.operand_column {
+            Some(column) => {
+                let (operand, _) = value_per_field.get(column)
+                    .ok_or(anyhow::anyhow!("{} has no column {column}", self.table_name))?;
+                Some(operand.as_str())
+            },
+            None => None,
+        };
+        lookup_table.accumulate(&self.store_key, self.function, str_value, data_type, operand)?;
+        Ok(true)
+    }
+
+    fn get_key(&self) -> &str {
+        &self.key
+    }
+
+    fn get_definition(&self) -> &str {
+        &self.store_key
+    }
+
+    fn get_table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    fn get_column_name(&self) -> &str {
+        &self.column_name
+    }
+
+    fn get_column_key(&self) -> &str {
+        &self.column_key
+    }
+
+    fn get_tracked_columns(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,66 @@
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use serde::Deserialize;
+
+use crate::checks::fake::Seeded;
+
+const DEFAULT_MAX_DAYS: u32 = 365;
+
+// Moves dates by whole days, the same number of days for every date of an entity, so that
+// the order and the intervals between the events of an entity are kept. Columns agree on the
+// offset when they share the secret and max_days.
+#[derive(Debug, Clone)]
+#[derive(Deserialize)]
+pub struct DateShiftSpec {
+    // the column holding the entity key, e.g. `user_id`
+    by: String,
+    max_days: Option<u32>,
+    secret: Option<String>,
+}
+
+impl DateShiftSpec {
+    pub fn entity_column(&self) -> &str {
+        &self.by
+    }
+
+    pub fn has_secret(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    pub fn secret_mut(&mut self) -> &mut Option<String> {
+        &mut self.secret
+    }
+
+    // never zero, a shift that keeps some of the dates would reveal them
+    fn offset(&self, entity: &str) -> TimeDelta {
+        let max_days = self.max_days.unwrap_or(DEFAULT_MAX_DAYS).max(1) as u64;
+        let mut seeded = Seeded::new(self.secret.as_deref().unwrap_or_default(), "date_shift", entity);
+        let days = (seeded.next_u64() % max_days + 1) as i64;
+        TimeDelta::days(if seeded.next_u64().is_multiple_of(2) { days } else { -days })
+    }
+
+    // shifts a `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS[.ffffff]` value, keeping its precision
+    pub fn shift(&self, value: &str, entity: &str) -> Result<String, anyhow::Error> {
+        // zero dates are no dates
+        if value.starts_with("0000-00-00") {
+            return Ok(value.to_owned());
+        }
+        let offset = self.offset(entity);
+        let out_of_range = || anyhow::anyhow!("shifting {value} leaves the range of dates");
+        if value.len() == 10 {
+            let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| anyhow::anyhow!("cannot parse date {value}"))?;
+            return Ok(date.checked_add_signed(offset).ok_or_else(out_of_range)?.format("%Y-%m-%d").to_string());
+        }
+        // whole days leave the fraction of a second as it is
+        let (seconds, fraction) = match value.split_once('.') {
+            Some((seconds, fraction)) => (seconds, Some(fraction)),
+            None => (value, None),
+        };
+        let datetime = NaiveDateTime::parse_from_str(seconds, "%Y-%m-%d %H:%M:%S")
+            .map_err(|_| anyhow::anyhow!("cannot parse timestamp {value}"))?;
+        let shifted = datetime.checked_add_signed(offset).ok_or_else(out_of_range)?.format("%Y-%m-%d %H:%M:%S").to_string();
+        Ok(match fraction {
+            Some(fraction) => format!("{shifted}.{fraction}"),
+            None => shifted,
+        })
+    }
+}
@@ -59,6 +59,18 @@ impl<T> DependencyNode<T>
         false
     }
 
+    pub fn depth_of(&self, key: &str) -> Option<usize> {
+        if self.get_key() == key {
+            return Some(0);
+        }
+        self.dependents.iter().find_map(|d| d.depth_of(key)).map(|depth| depth + 1)
+    }
+
+    // whether key is somewhere below the node of ancestor_key
+    pub fn is_under(&self, ancestor_key: &str, key: &str) -> bool {
+        self.get_node(ancestor_key).is_some_and(|node| node.dependents.iter().any(|d| d.has_child(key)))
+    }
+
     pub fn add_child_to_group(&mut self, payload: T, group_key: &str) -> Result<(), anyhow::Error> {
         let key = (&payload).into().to_string();
 
@@ -87,6 +99,13 @@ impl<T> DependencyNode<T>
         }
     }
 
+    fn get_node(&self, key: &str) -> Option<&DependencyNode<T>> {
+        if self.get_key() == key {
+            return Some(self);
+        }
+        self.dependents.iter().find_map(|dep| dep.get_node(key))
+    }
+
     fn get_node_mut<'a>(&'a mut self, key: &str) -> Option<&'a mut DependencyNode<T>> {
         if self.get_key() == key {
             return Some(self);
@@ -0,0 +1,157 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+const FIRST_NAMES: &[&str] = &[
+    "Ada", "Alan", "Alice", "Amir", "Anna", "Ben", "Carla", "Chen", "Clara", "Daniel", "Dora", "Elena", "Emil",
+    "Eva", "Farah", "Felix", "Grace", "Hana", "Hugo", "Ines", "Ivan", "Jonas", "Julia", "Kai", "Kenji", "Lara",
+    "Leon", "Lina", "Luca", "Maya", "Mila", "Nadia", "Noah", "Omar", "Oscar", "Paula", "Petra", "Rosa", "Sam",
+    "Sara", "Tariq", "Theo", "Uma", "Vera", "Victor", "Wanda", "Yara", "Yusuf", "Zoe", "Zoran",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Adams", "Baker", "Berg", "Brooks", "Campbell", "Costa", "Dahl", "Evans", "Fischer", "Garcia", "Gray",
+    "Hansen", "Hughes", "Ito", "Jensen", "Kaur", "Keller", "Khan", "Kowalski", "Lambert", "Lopez", "Meyer",
+    "Moreau", "Murphy", "Nakamura", "Novak", "Olsen", "Park", "Patel", "Perez", "Quinn", "Rossi", "Schmidt",
+    "Silva", "Singh", "Smith", "Tanaka", "Torres", "Varga", "Wagner", "Walsh", "Weber", "Young", "Zhang",
+];
+
+const STREETS: &[&str] = &[
+    "Acacia", "Birch", "Cedar", "Chestnut", "Elm", "Harbor", "Hill", "Lake", "Linden", "Maple", "Meadow",
+    "Mill", "Oak", "Orchard", "Park", "Pine", "River", "Spring", "Station", "Sunset", "Valley", "Willow",
+];
+
+const STREET_SUFFIXES: &[&str] = &["Street", "Road", "Avenue", "Lane", "Way", "Drive", "Court", "Place"];
+
+const COMPANY_WORDS: &[&str] = &[
+    "Apex", "Blue", "Bright", "Cobalt", "Delta", "Evergreen", "First", "Granite", "Harbor", "Iron", "Keystone",
+    "Lumen", "Meridian", "North", "Nova", "Orbit", "Pioneer", "Quantum", "Silver", "Summit", "Vertex", "Zenith",
+];
+
+const COMPANY_SUFFIXES: &[&str] = &["Ltd", "Inc", "GmbH", "LLC", "Group", "Partners", "Systems", "Labs"];
+
+const LOREM: &[&str] = &[
+    "lorem", "ipsum", "dolor", "sit", "amet", "consectetur", "adipiscing", "elit", "sed", "do", "eiusmod",
+    "tempor", "incididunt", "ut", "labore", "et", "dolore", "magna", "aliqua", "enim", "ad", "minim", "veniam",
+    "quis", "nostrud", "exercitation", "ullamco", "laboris", "nisi", "aliquip", "ex", "ea", "commodo",
+];
+
+const EMAIL_DOMAINS: &[&str] = &["example.com", "example.net", "example.org"];
+
+#[derive(Debug, Clone, Copy)]
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FakeKind {
+    FirstName,
+    LastName,
+    Name,
+    Email,
+    // in the fictional 555 range, so only 10^7 numbers
+    Phone,
+    Address,
+    Company,
+    Iban,
+    Lorem,
+}
+
+// a stream of pseudo random numbers derived from the secret, a label and the original value
+pub struct Seeded {
+    seed: [u8; 32],
+    block: [u8; 32],
+    position: usize,
+    counter: u64,
+}
+
+impl Seeded {
+    pub fn new(secret: &str, label: &str, value: &str) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(secret.as_bytes());
+        hasher.update([0]);
+        hasher.update(label.as_bytes());
+        hasher.update([0]);
+        hasher.update(value.as_bytes());
+        let seed: [u8; 32] = hasher.finalize().into();
+        Seeded { seed, block: seed, position: 0, counter: 0 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        if self.position + 8 > self.block.len() {
+            self.counter += 1;
+            let mut hasher = Sha256::new();
+            hasher.update(self.seed);
+            hasher.update(self.counter.to_le_bytes());
+            self.block = hasher.finalize().into();
+            self.position = 0;
+        }
+        let bytes: [u8; 8] = self.block[self.position..self.position + 8].try_into().unwrap();
+        self.position += 8;
+        u64::from_le_bytes(bytes)
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[(self.next_u64() % items.len() as u64) as usize]
+    }
+
+    fn digits(&mut self, count: usize) -> String {
+        (0..count).map(|_| char::from(b'0' + (self.next_u64() % 10) as u8)).collect()
+    }
+}
+
+fn to_base36(mut value: u64) -> String {
+    let mut digits = Vec::new();
+    loop {
+        let digit = (value % 36) as u32;
+        digits.push(char::from_digit(digit, 36).unwrap());
+        value /= 36;
+        if value == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().collect()
+}
+
+// ISO 13616 check digits, so that the fake numbers pass a format validation
+fn iban_check_digits(country: &str, bban: &str) -> String {
+    let rearranged = String::from(bban) + country + "00";
+    let remainder = rearranged.chars().fold(0u64, |remainder, c| {
+        let value = c.to_digit(36).unwrap() as u64;
+        let shift = if value >= 10 { 100 } else { 10 };
+        (remainder * shift + value) % 97
+    });
+    format!("{:02}", 98 - remainder)
+}
+
+impl FakeKind {
+    // The same value and secret always give the same fake. Emails and IBANs carry 64 bits of
+    // the hash and stay unique unless they are cut to the column size; names, phones,
+    // addresses, companies and lorem text come from small sets and collide, so they cannot
+    // replace a unique column.
+    pub fn generate(&self, secret: &str, value: &str) -> String {
+        let mut seeded = Seeded::new(secret, &format!("{self:?}"), value);
+        match self {
+            FakeKind::FirstName => seeded.pick(FIRST_NAMES).to_owned(),
+            FakeKind::LastName => seeded.pick(LAST_NAMES).to_owned(),
+            FakeKind::Name => format!("{} {}", seeded.pick(FIRST_NAMES), seeded.pick(LAST_NAMES)),
+            FakeKind::Email => {
+                let (first, last) = (seeded.pick(FIRST_NAMES), seeded.pick(LAST_NAMES));
+                let domain = seeded.pick(EMAIL_DOMAINS);
+                format!("{}.{}.{}@{domain}", first.to_lowercase(), last.to_lowercase(), to_base36(seeded.next_u64()))
+            },
+            FakeKind::Phone => format!("+1-555-{}-{}", seeded.digits(3), seeded.digits(4)),
+            FakeKind::Address => {
+                let number = seeded.next_u64() % 999 + 1;
+                format!("{number} {} {}", seeded.pick(STREETS), seeded.pick(STREET_SUFFIXES))
+            },
+            FakeKind::Company => format!("{} {} {}", seeded.pick(COMPANY_WORDS), seeded.pick(LAST_NAMES), seeded.pick(COMPANY_SUFFIXES)),
+            FakeKind::Iban => {
+                let bban = String::from("FAKE") + &format!("{:020}", seeded.next_u64());
+                format!("XX{}{bban}", iban_check_digits("XX", &bban))
+            },
+            FakeKind::Lorem => {
+                // about as many words as the original text
+                let count = value.split_whitespace().count().clamp(1, 200);
+                let words: Vec<&str> = (0..count).map(|_| seeded.pick(LOREM)).collect();
+                words.join(" ")
+            },
+        }
+    }
+}
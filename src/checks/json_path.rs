@@ -0,0 +1,164 @@
+use serde_json::Value as JsonValue;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+    // every member of an object or element of an array
+    Wildcard,
+}
+
+// A subset of JSONPath: `$.contact.email`, `$['first name']`, `$.phones[0]`, `$.items[*].sku`
+#[derive(Debug, Clone)]
+pub struct JsonPath(Vec<Step>);
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<Self, anyhow::Error> {
+        let Some(mut rest) = path.trim().strip_prefix('$') else {
+            return Err(anyhow::anyhow!("JSON path {path} has to start with $"));
+        };
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after_dot) = rest.strip_prefix('.') {
+                let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+                let key = &after_dot[..end];
+                steps.push(match key {
+                    "" => return Err(anyhow::anyhow!("empty key in JSON path {path}")),
+                    "*" => Step::Wildcard,
+                    key => Step::Key(key.to_owned()),
+                });
+                rest = &after_dot[end..];
+            } else if let Some(after_bracket) = rest.strip_prefix('[') {
+                let selector = after_bracket.trim_start();
+                // a quoted key may hold dots and brackets, it ends at its closing quote
+                let (step, after_selector) = match selector.chars().next() {
+                    Some(quote @ ('\'' | '"')) => {
+                        let Some(end) = selector[1..].find(quote) else {
+                            return Err(anyhow::anyhow!("unclosed quote in JSON path {path}"));
+                        };
+                        (Step::Key(selector[1..end + 1].to_owned()), &selector[end + 2..])
+                    },
+                    _ => {
+                        let end = selector.find(']').unwrap_or(selector.len());
+                        let step = match selector[..end].trim() {
+                            "*" => Step::Wildcard,
+                            index => Step::Index(index.parse().map_err(|_| anyhow::anyhow!("invalid index {index} in JSON path {path}"))?),
+                        };
+                        (step, &selector[end..])
+                    },
+                };
+                let Some(after_selector) = after_selector.trim_start().strip_prefix(']') else {
+                    return Err(anyhow::anyhow!("unclosed [ in JSON path {path}"));
+                };
+                steps.push(step);
+                rest = after_selector;
+            } else {
+                return Err(anyhow::anyhow!("unexpected {rest} in JSON path {path}"));
+            }
+        }
+        if steps.is_empty() {
+            return Err(anyhow::anyhow!("JSON path {path} selects the whole document, transform the column instead"));
+        }
+        Ok(JsonPath(steps))
+    }
+
+    // the nodes the path points to, paths that do not exist in the document select nothing
+    pub fn select_mut<'a>(&self, document: &'a mut JsonValue) -> Vec<&'a mut JsonValue> {
+        let mut selected = Vec::new();
+        JsonPath::collect(&self.0, document, &mut selected);
+        selected
+    }
+
+    fn collect<'a>(steps: &[Step], node: &'a mut JsonValue, selected: &mut Vec<&'a mut JsonValue>) {
+        let Some((step, rest)) = steps.split_first() else {
+            selected.push(node);
+            return;
+        };
+        match (step, node) {
+            (Step::Key(key), JsonValue::Object(members)) => {
+                if let Some(child) = members.get_mut(key) {
+                    JsonPath::collect(rest, child, selected);
+                }
+            },
+            (Step::Index(index), JsonValue::Array(elements)) => {
+                if let Some(child) = elements.get_mut(*index) {
+                    JsonPath::collect(rest, child, selected);
+                }
+            },
+            (Step::Wildcard, JsonValue::Object(members)) => {
+                for child in members.values_mut() {
+                    JsonPath::collect(rest, child, selected);
+                }
+            },
+            (Step::Wildcard, JsonValue::Array(elements)) => {
+                for child in elements.iter_mut() {
+                    JsonPath::collect(rest, child, selected);
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn steps(path: &str) -> Vec<Step> {
+        JsonPath::parse(path).unwrap().0
+    }
+
+    fn key(key: &str) -> Step {
+        Step::Key(key.to_owned())
+    }
+
+    #[test]
+    fn parses_dotted_and_bracket_paths() {
+        assert_eq!(steps("$.contact.email"), [key("contact"), key("email")]);
+        assert_eq!(steps(" $.phones[0] "), [key("phones"), Step::Index(0)]);
+        assert_eq!(steps("$[ 12 ].a"), [Step::Index(12), key("a")]);
+        assert_eq!(steps("$.items[*].sku"), [key("items"), Step::Wildcard, key("sku")]);
+        assert_eq!(steps("$.*"), [Step::Wildcard]);
+    }
+
+    #[test]
+    fn parses_quoted_keys() {
+        assert_eq!(steps("$['first name']"), [key("first name")]);
+        assert_eq!(steps(r#"$["first name"].x"#), [key("first name"), key("x")]);
+        assert_eq!(steps("$['a.b']"), [key("a.b")]);
+        assert_eq!(steps("$['a]b'][0]"), [key("a]b"), Step::Index(0)]);
+        assert_eq!(steps(r#"$["it's"]"#), [key("it's")]);
+        assert_eq!(steps("$['*']"), [key("*")]);
+        assert_eq!(steps("$['']"), [key("")]);
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        for path in ["contact.email", "$", "$.", "$..a", "$[0", "$['a'", "$['a'x]", "$[-1]", "$[a]", "$a"] {
+            assert!(JsonPath::parse(path).is_err(), "{path}");
+        }
+    }
+
+    #[test]
+    fn selects_existing_nodes_only() {
+        let mut document = json!({
+            "contact": {"email": "a@example.com"},
+            "items": [{"sku": "A"}, {"sku": "B"}, {"name": "no sku"}],
+            "first name": "Jane",
+        });
+        let path = JsonPath::parse("$.items[*].sku").unwrap();
+        let selected: Vec<JsonValue> = path.select_mut(&mut document).into_iter().map(|node| node.clone()).collect();
+        assert_eq!(selected, [json!("A"), json!("B")]);
+
+        for node in JsonPath::parse("$['first name']").unwrap().select_mut(&mut document) {
+            *node = json!("X");
+        }
+        assert_eq!(document["first name"], "X");
+
+        assert!(JsonPath::parse("$.contact.phone").unwrap().select_mut(&mut document).is_empty());
+        assert!(JsonPath::parse("$.items[5].sku").unwrap().select_mut(&mut document).is_empty());
+        assert!(JsonPath::parse("$.contact[0]").unwrap().select_mut(&mut document).is_empty());
+        assert_eq!(JsonPath::parse("$.contact.*").unwrap().select_mut(&mut document).len(), 1);
+    }
+}
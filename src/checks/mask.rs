@@ -0,0 +1,174 @@
+use serde::Deserialize;
+
+use crate::checks::fake::Seeded;
+
+// number of mask characters a masked part collapses to when the length is not kept
+const COLLAPSED_LENGTH: usize = 3;
+
+// Masks a value while keeping its shape: kept prefix and suffix, digits replaced by digits and
+// letters by letters of the same case, separators left in place.
+#[derive(Debug, Clone, Default)]
+#[derive(Deserialize)]
+pub struct MaskSpec {
+    keep_prefix: Option<usize>,
+    keep_suffix: Option<usize>,
+    // replace masked characters with this one instead of one of the same class
+    with: Option<char>,
+    keep_length: Option<bool>,
+    // mask only the part before the @ of an email
+    keep_domain: Option<bool>,
+    // fix a masked digit so that the number passes the Luhn check
+    luhn: Option<bool>,
+    secret: Option<String>,
+}
+
+fn luhn_sum(digits: &[u32]) -> u32 {
+    digits.iter().rev().enumerate().map(|(position, digit)| {
+        if position % 2 == 1 {
+            let doubled = digit * 2;
+            if doubled > 9 { doubled - 9 } else { doubled }
+        } else {
+            *digit
+        }
+    }).sum()
+}
+
+impl MaskSpec {
+    pub fn produces_digits(&self) -> bool {
+        self.with.is_none_or(|c| c.is_ascii_digit()) && self.keep_length.unwrap_or(true) && !self.keep_domain.unwrap_or(false)
+    }
+
+    pub fn secret_mut(&mut self) -> &mut Option<String> {
+        &mut self.secret
+    }
+
+    pub fn apply(&self, value: &str) -> Result<String, anyhow::Error> {
+        if self.keep_domain.unwrap_or(false) && let Some((local, domain)) = value.rsplit_once('@') {
+            return Ok(self.mask(local)? + "@" + domain);
+        }
+        self.mask(value)
+    }
+
+    fn mask(&self, value: &str) -> Result<String, anyhow::Error> {
+        let chars: Vec<char> = value.chars().collect();
+        let prefix = self.keep_prefix.unwrap_or(0).min(chars.len());
+        let suffix = self.keep_suffix.unwrap_or(0).min(chars.len() - prefix);
+        let masked_range = prefix..chars.len() - suffix;
+        let mut seeded = Seeded::new(self.secret.as_deref().unwrap_or_default(), "mask", value);
+
+        let mut masked: Vec<char> = chars[..prefix].to_vec();
+        if self.keep_length.unwrap_or(true) {
+            for c in chars[masked_range.clone()].iter() {
+                masked.push(match (self.with, c) {
+                    (_, c) if !c.is_alphanumeric() => *c,
+                    (Some(with), _) => with,
+                    (None, c) if c.is_ascii_digit() => char::from(b'0' + (seeded.next_u64() % 10) as u8),
+                    (None, c) if c.is_uppercase() => char::from(b'A' + (seeded.next_u64() % 26) as u8),
+                    (None, _) => char::from(b'a' + (seeded.next_u64() % 26) as u8),
+                });
+            }
+        } else if !masked_range.is_empty() {
+            masked.extend(std::iter::repeat_n(self.with.unwrap_or('*'), COLLAPSED_LENGTH));
+        }
+        masked.extend_from_slice(&chars[chars.len() - suffix..]);
+
+        if self.luhn.unwrap_or(false) {
+            let masked_end = masked.len() - suffix;
+            MaskSpec::fix_luhn(&mut masked, prefix..masked_end)?;
+        }
+        Ok(masked.into_iter().collect())
+    }
+
+    // changes the rightmost masked digit until the whole number is Luhn valid
+    fn fix_luhn(masked: &mut [char], masked_range: std::ops::Range<usize>) -> Result<(), anyhow::Error> {
+        let Some(position) = masked_range.rev().find(|position| masked[*position].is_ascii_digit()) else {
+            return Err(anyhow::anyhow!("no masked digit left to make {} Luhn valid", masked.iter().collect::<String>()));
+        };
+        for candidate in 0..10 {
+            masked[position] = char::from_digit(candidate, 10).unwrap();
+            let digits: Vec<u32> = masked.iter().filter_map(|c| c.to_digit(10)).collect();
+            if luhn_sum(&digits).is_multiple_of(10) {
+                return Ok(());
+            }
+        }
+        unreachable!("one of ten digits always completes the Luhn sum")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(json: &str) -> MaskSpec {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn is_luhn_valid(number: &str) -> bool {
+        let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+        luhn_sum(&digits).is_multiple_of(10)
+    }
+
+    #[test]
+    fn luhn_sum_of_known_numbers() {
+        assert!(is_luhn_valid("4111 1111 1111 1111"));
+        assert!(is_luhn_valid("79927398713"));
+        assert!(!is_luhn_valid("79927398710"));
+    }
+
+    #[test]
+    fn keeps_shape_prefix_and_suffix() {
+        let masked = spec(r#"{"keep_prefix": 2, "keep_suffix": 3}"#).apply("Ab-12cD/xyz").unwrap();
+        assert_eq!(masked.len(), 11);
+        assert!(masked.starts_with("Ab-"));
+        assert!(masked.ends_with("/xyz"));
+        let middle: Vec<char> = masked[3..7].chars().collect();
+        assert!(middle[0].is_ascii_digit() && middle[1].is_ascii_digit());
+        assert!(middle[2].is_ascii_lowercase() && middle[3].is_ascii_uppercase());
+    }
+
+    #[test]
+    fn same_value_and_secret_give_the_same_mask() {
+        let mask = spec(r#"{"secret": "s"}"#);
+        assert_eq!(mask.apply("secret value").unwrap(), mask.apply("secret value").unwrap());
+        assert_ne!(mask.apply("secret value").unwrap(), spec(r#"{"secret": "t"}"#).apply("secret value").unwrap());
+    }
+
+    #[test]
+    fn replaces_with_a_fixed_character_or_collapses() {
+        assert_eq!(spec(r#"{"with": "*", "keep_suffix": 4}"#).apply("4111-1111-1111-1234").unwrap(), "****-****-****-1234");
+        assert_eq!(spec(r#"{"keep_length": false, "keep_prefix": 1}"#).apply("Johnathan").unwrap(), "J***");
+        assert_eq!(spec(r#"{"keep_length": false, "with": "-"}"#).apply("").unwrap(), "");
+        // prefix and suffix longer than the value keep it as it is
+        assert_eq!(spec(r#"{"keep_prefix": 4, "keep_suffix": 4}"#).apply("abc").unwrap(), "abc");
+    }
+
+    #[test]
+    fn masks_the_local_part_of_emails_only() {
+        let masked = spec(r#"{"keep_domain": true, "with": "x", "keep_prefix": 1}"#).apply("jane.doe@example.com").unwrap();
+        assert_eq!(masked, "jxxx.xxx@example.com");
+        // without an @ the whole value is masked
+        assert_eq!(spec(r#"{"keep_domain": true, "with": "x"}"#).apply("jane").unwrap(), "xxxx");
+    }
+
+    #[test]
+    fn luhn_fix_up_makes_card_numbers_valid() {
+        for (secret, card) in [("a", "4111 1111 1111 1111"), ("b", "5500-0000-0000-0004"), ("c", "378282246310005")] {
+            let mask = spec(&format!(r#"{{"keep_prefix": 6, "keep_suffix": 4, "luhn": true, "secret": "{secret}"}}"#));
+            let masked = mask.apply(card).unwrap();
+            assert!(is_luhn_valid(&masked), "{masked}");
+            assert_eq!(masked.len(), card.len());
+            assert_eq!(masked[..6], card[..6]);
+            assert_eq!(masked[card.len() - 4..], card[card.len() - 4..]);
+        }
+        // the last digits are kept, the rightmost masked digit is the one fixed
+        let masked = spec(r#"{"keep_suffix": 1, "luhn": true}"#).apply("7992739871").unwrap();
+        assert!(is_luhn_valid(&masked));
+        assert!(masked.ends_with('1'));
+    }
+
+    #[test]
+    fn luhn_fix_up_needs_a_masked_digit() {
+        assert!(spec(r#"{"keep_prefix": 4, "luhn": true}"#).apply("1234").is_err());
+        assert!(spec(r#"{"with": "*", "luhn": true}"#).apply("1234").is_err());
+    }
+}
@@ -1,15 +1,194 @@
+mod aggregate;
+mod date_shift;
 mod dependencies;
-
-use cel_interpreter::{Context, Program};
+mod fake;
+mod json_path;
+mod mask;
+mod patterns;
+mod pseudonym;
+mod renumber;
+mod seed;
+mod transforms;
+mod vault;
+
+use cel_interpreter::{Context, ExecutionError, Program, ResolveResult, Value as CelValue};
+use cel_interpreter::extractors::Arguments;
 use chrono::NaiveDateTime;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
 use std::any::Any;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
+use crate::checks::aggregate::{AggregateReference, PlainAggregateTest};
 use crate::checks::dependencies::{DependencyNode, chunk_by_depth};
+use crate::lookup::LookupStore;
+use crate::parameters::{self, Parameter};
+
+pub use crate::checks::patterns::{condition_columns, expand_table_patterns, is_table_pattern};
+pub use crate::checks::pseudonym::KeySource;
+pub use crate::checks::renumber::{RenumberConfig, Renumbering};
+pub use crate::checks::seed::{SeedConfig, SeedWalk};
+pub use crate::checks::transforms::{Transform, check_transforms};
+pub use crate::checks::vault::{VaultConfig, load_vault, save_vault};
+
+use crate::checks::transforms::ColumnTransforms;
 
 pub type PlainCheckType = Box<dyn PlainColumnCheck>;
 
+type ValuesMap = HashMap<String, (String, sqlparser::ast::DataType)>;
+
+lazy_static! {
+    static ref NULL: String = String::from("NULL");
+    static ref TRACKED_RE: Regex = Regex::new(r#"tracked\(\s*['"]([^'"]+)['"]\s*\)"#).unwrap();
+}
+
+// cascades can point to key sets loaded from files instead of a table column
+pub const FILE_KEY_PREFIX: &str = "@file:";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnMissing {
+    #[default]
+    Drop,
+    SetNull,
+    Keep,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum RuleGroup {
+    Any { any: Vec<Condition> },
+    All { all: Vec<Condition> },
+    Not { not: Box<Condition> },
+    // a reference to one of the named rule sets, replaced by its rules on startup
+    Named { rule_set: String },
+}
+
+#[derive(Debug, Clone)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Condition {
+    Group(RuleGroup),
+    Plain(String),
+    Detailed { definition: String, on_missing: Option<OnMissing>, drop_null: Option<bool> },
+    Polymorphic {
+        column: String,
+        type_column: String,
+        targets: HashMap<String, String>,
+        on_missing: Option<OnMissing>,
+        drop_null: Option<bool>,
+    },
+}
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct JoinTable {
+    sides: Vec<String>,
+    // sides that also keep the rows surviving join rows reference, whatever their own filters say
+    pull: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct LookupPolicy {
+    on_missing: OnMissing,
+    drop_null: bool,
+    strict: bool,
+    // treat a lookup set that was never tracked as empty instead of skipping the check
+    required: bool,
+    // keeps the row whatever the other checks of its table say
+    pull: bool,
+}
+
+impl RuleGroup {
+    fn branches(&self) -> Vec<&Condition> {
+        match self {
+            RuleGroup::Any { any } => any.iter().collect(),
+            RuleGroup::All { all } => all.iter().collect(),
+            RuleGroup::Not { not } => Vec::from([&**not]),
+            RuleGroup::Named { .. } => Vec::new(),
+        }
+    }
+
+    fn branches_mut(&mut self) -> Vec<&mut Condition> {
+        match self {
+            RuleGroup::Any { any } => any.iter_mut().collect(),
+            RuleGroup::All { all } => all.iter_mut().collect(),
+            RuleGroup::Not { not } => Vec::from([&mut **not]),
+            RuleGroup::Named { .. } => Vec::new(),
+        }
+    }
+}
+
+impl Condition {
+    // definitions of the condition, groups yield those of all their branches
+    pub fn definitions(&self) -> Vec<&str> {
+        match self {
+            Condition::Group(group) => group.branches().into_iter().flat_map(|c| c.definitions()).collect(),
+            Condition::Plain(definition) => Vec::from([definition.as_str()]),
+            Condition::Detailed { definition, .. } => Vec::from([definition.as_str()]),
+            Condition::Polymorphic { column, .. } => Vec::from([column.as_str()]),
+        }
+    }
+
+    // columns of the table the condition refers to
+    fn columns(&self) -> Result<Vec<String>, anyhow::Error> {
+        match self {
+            Condition::Group(group) => {
+                let mut columns = Vec::new();
+                for branch in group.branches() {
+                    columns.extend(branch.columns()?);
+                }
+                Ok(columns)
+            },
+            Condition::Polymorphic { column, type_column, .. } => Ok(Vec::from([column.to_owned(), type_column.to_owned()])),
+            Condition::Plain(definition) | Condition::Detailed { definition, .. } if definition.contains("->") => {
+                Ok(Vec::from([PlainLookupTest::get_column_info(definition)?.0]))
+            },
+            Condition::Plain(definition) | Condition::Detailed { definition, .. } => PlainCelTest::get_columns(definition),
+        }
+    }
+
+    fn resolve(&mut self, rule_sets: &HashMap<String, Vec<Condition>>, resolving: &mut Vec<String>) -> Result<(), anyhow::Error> {
+        let Condition::Group(group) = self else { return Ok(()) };
+
+        if let RuleGroup::Named { rule_set } = group {
+            if resolving.contains(rule_set) {
+                return Err(anyhow::anyhow!("rule set {rule_set} references itself"));
+            }
+            let all = rule_sets.get(rule_set).ok_or(anyhow::anyhow!("unknown rule set {rule_set}"))?.to_owned();
+            resolving.push(rule_set.to_owned());
+            *group = RuleGroup::All { all };
+            for branch in group.branches_mut() {
+                branch.resolve(rule_sets, resolving)?;
+            }
+            resolving.pop();
+            return Ok(());
+        }
+
+        for branch in group.branches_mut() {
+            branch.resolve(rule_sets, resolving)?;
+        }
+        Ok(())
+    }
+
+    fn policy(&self, strict: bool) -> LookupPolicy {
+        match self {
+            Condition::Plain(_) | Condition::Group(_) => LookupPolicy { strict, ..Default::default() },
+            Condition::Detailed { on_missing, drop_null, .. } | Condition::Polymorphic { on_missing, drop_null, .. } => LookupPolicy {
+                on_missing: on_missing.unwrap_or_default(),
+                drop_null: drop_null.unwrap_or(false),
+                strict,
+                ..Default::default()
+            },
+        }
+    }
+}
+
 enum Value {
     Int(i64),
     Date(i64),
@@ -61,9 +240,18 @@ pub trait PlainColumnCheck {
         &self,
         value: &str,
         data_type: &sqlparser::ast::DataType,
-        lookup_table: &mut HashMap<String, HashSet<String>>,
+        lookup_table: &mut LookupStore,
     ) -> Result<bool, anyhow::Error>;
 
+    fn test_row(
+        &self,
+        value_per_field: &ValuesMap,
+        lookup_table: &mut LookupStore,
+    ) -> Result<bool, anyhow::Error> {
+        let (str_value, data_type) = &value_per_field[self.get_column_name()];
+        self.test_value(str_value, data_type, lookup_table)
+    }
+
     fn get_table_name(&self) -> &str;
 
     fn get_column_name(&self) -> &str;
@@ -98,15 +286,63 @@ pub struct PlainCelTest {
     column_name: String,
     column_key: String,
     definition: String,
+    variables: Vec<String>,
+    uses_parameters: bool,
+    tracked_keys: Vec<String>,
+    aggregates: Vec<AggregateReference>,
     program: Program,
 }
 
 impl PlainCelTest {
+    fn get_tracked_keys(definition: &str) -> Vec<String> {
+        TRACKED_RE.captures_iter(definition).map(|captures| captures[1].to_owned()).collect()
+    }
+
+    // referenced variables that are columns, config parameters are bound separately
+    fn get_variables(program: &Program) -> Vec<String> {
+        let mut variables: Vec<String> = program.references().variables().iter()
+            .filter(|f| **f != parameters::NAMESPACE)
+            .map(|f| f.to_string())
+            .collect();
+        variables.sort();
+        variables
+    }
+
+    fn uses_parameters(program: &Program) -> bool {
+        program.references().variables().contains(&parameters::NAMESPACE)
+    }
+
+    // `params.name` is the parameter as declared, `'007'` stays a string
+    pub fn parameters_value() -> CelValue {
+        let values: HashMap<String, CelValue> = parameters::get_all().iter().map(|(name, parameter)| {
+            let value = match parameter {
+                Parameter::Bool(value) => CelValue::Bool(*value),
+                Parameter::Int(value) => CelValue::Int(*value),
+                Parameter::Float(value) => CelValue::Float(*value),
+                Parameter::Text(value) => CelValue::String(Arc::new(value.to_owned())),
+            };
+            (name.to_owned(), value)
+        }).collect();
+        values.into()
+    }
+
+    fn get_column_name(variables: &[String], aggregates: &[AggregateReference]) -> String {
+        // a test on aggregates alone is about the column the groups are matched by,
+        // a constant one like `true` about no column at all
+        variables.first().or(aggregates.first().map(|a| &a.column)).cloned().unwrap_or_default()
+    }
+
+    fn get_columns(definition: &str) -> Result<Vec<String>, anyhow::Error> {
+        let mut columns = PlainCelTest::get_variables(&Program::compile(definition)?);
+        columns.extend(AggregateReference::find_all(definition)?.into_iter().map(|a| a.column));
+        Ok(columns)
+    }
+
     pub fn get_column_info(definition: &str) -> Result<(String, Vec<String>), anyhow::Error> {
         let program = Program::compile(definition)?;
-        let variables: Vec<String> = program.references().variables().iter().map(|f| f.to_string()).collect();
-        let column_name = &variables[0];
-        Ok((column_name.to_owned(), Vec::new()))
+        let variables = PlainCelTest::get_variables(&program);
+        let column_name = PlainCelTest::get_column_name(&variables, &AggregateReference::find_all(definition)?);
+        Ok((column_name, PlainCelTest::get_tracked_keys(definition)))
     }
 
     fn parse_date(s: &str) -> i64 {
@@ -117,20 +353,87 @@ impl PlainCelTest {
             .timestamp()
     }
 
-    fn build_context(&self, column_name: &str, str_value: &str, data_type: &sqlparser::ast::DataType) -> Result<Context, anyhow::Error> {
-        let value: Value = Value::parse(str_value, data_type);
+    fn to_cel_value(value: Value) -> CelValue {
+        match value {
+            Value::Int(parsed) => CelValue::Int(parsed),
+            Value::Date(parsed) => CelValue::Int(parsed),
+            Value::String(parsed) => CelValue::String(Arc::new(parsed)),
+            Value::Null => CelValue::Bool(false),
+        }
+    }
+
+    fn build_context(&self, value_per_field: &ValuesMap, lookup_table: &LookupStore) -> Result<Context<'_>, anyhow::Error> {
         let mut context = Context::default();
         context.add_function("timestamp", |d: Arc<String>| {
             PlainCelTest::parse_date(&d)
         });
 
-        let e = anyhow::anyhow!("Cannot add variable to context");
-        match value {
-            Value::Int(parsed) => context.add_variable(column_name, parsed),
-            Value::Date(parsed) => context.add_variable(column_name, parsed),
-            Value::String(parsed) => context.add_variable(column_name, parsed),
-            Value::Null => context.add_variable(column_name, false),
-        }.map_err(|_| e)?;
+        let mut columns = Vec::new();
+        for variable in self.variables.iter() {
+            // variables bound by macros are not columns
+            let Some((str_value, data_type)) = value_per_field.get(variable) else { continue };
+            context.add_variable_from_value(variable, PlainCelTest::to_cel_value(Value::parse(str_value, data_type)));
+            columns.push((str_value, data_type));
+        }
+        if self.uses_parameters {
+            context.add_variable_from_value(parameters::NAMESPACE, PlainCelTest::parameters_value());
+        }
+
+        if !self.aggregates.is_empty() {
+            // `agg(key, function)` looks up the group of this row, which is known before the
+            // row is tested, as the groups are complete once the grouped table was processed
+            let mut results: HashMap<String, CelValue> = HashMap::new();
+            for reference in self.aggregates.iter() {
+                let (str_value, data_type) = value_per_field.get(&reference.column)
+                    .ok_or(anyhow::anyhow!("{} has no column {}", self.table_name, reference.column))?;
+                let result = match lookup_table.aggregate(&reference.store_key(), str_value, data_type) {
+                    Some(result) if result.fract() == 0.0 && result.abs() < i64::MAX as f64 => CelValue::Int(result as i64),
+                    Some(result) => CelValue::Float(result),
+                    // no rows in the group
+                    None if reference.spec == "count" || reference.spec.starts_with("sum:") => CelValue::Int(0),
+                    None => CelValue::Null,
+                };
+                results.insert(AggregateReference::call_key(reference.kept, &reference.key, &reference.spec, Some(&reference.column)), result);
+            }
+            let results = Arc::new(results);
+            for (name, kept) in [("agg", false), ("kept_agg", true)] {
+                let results = Arc::clone(&results);
+                context.add_function(name, move |Arguments(args): Arguments| -> ResolveResult {
+                    let literals: Vec<&str> = args.iter().filter_map(|arg| match arg {
+                        CelValue::String(literal) => Some(literal.as_str()),
+                        _ => None,
+                    }).collect();
+                    let (Some(key), Some(spec)) = (literals.first(), literals.get(1)) else {
+                        return Err(ExecutionError::function_error(name, "expects a key and a function"));
+                    };
+                    match results.get(&AggregateReference::call_key(kept, key, spec, literals.get(2).copied())) {
+                        Some(result) => Ok(result.to_owned()),
+                        None => Err(ExecutionError::function_error(name, format!("{key} {spec} is not referenced literally"))),
+                    }
+                });
+            }
+        }
+
+        if !self.tracked_keys.is_empty() {
+            // `tracked(key)` lists the values of this row that are in the key set,
+            // so `column in tracked(key)` holds exactly when the column value was tracked
+            let mut members: HashMap<String, Vec<CelValue>> = HashMap::new();
+            for key in self.tracked_keys.iter() {
+                let mut values = Vec::new();
+                for (str_value, data_type) in columns.iter() {
+                    if lookup_table.contains(key, str_value, data_type)? {
+                        values.push(PlainCelTest::to_cel_value(Value::parse(str_value, data_type)));
+                    }
+                }
+                members.insert(key.to_owned(), values);
+            }
+            context.add_function("tracked", move |key: Arc<String>| -> ResolveResult {
+                match members.get(key.as_str()) {
+                    Some(values) => Ok(CelValue::List(Arc::new(values.to_owned()))),
+                    None => Err(ExecutionError::function_error("tracked", format!("{key} is not referenced literally"))),
+                }
+            });
+        }
 
         Ok(context)
     }
@@ -138,9 +441,10 @@ impl PlainCelTest {
 
 impl PlainColumnCheck for PlainCelTest {
     fn new(definition: &str, table: &str) -> Result<impl PlainColumnCheck + 'static, anyhow::Error> where Self: Sized {
-        let program = Program::compile(definition).unwrap();
-        let variables: Vec<String> = program.references().variables().iter().map(|f| f.to_string()).collect();
-        let column = &variables[0];
+        let program = Program::compile(definition).map_err(|e| anyhow::anyhow!("cannot compile {definition}: {e}"))?;
+        let variables = PlainCelTest::get_variables(&program);
+        let aggregates = AggregateReference::find_all(definition)?;
+        let column = &PlainCelTest::get_column_name(&variables, &aggregates);
 
         Ok(PlainCelTest {
             key: String::from("cel: ") + table + ": " + definition,
@@ -148,6 +452,10 @@ impl PlainColumnCheck for PlainCelTest {
             column_name: column.to_owned(),
             column_key: String::from(table) + "." +column,
             definition: definition.to_owned(),
+            tracked_keys: PlainCelTest::get_tracked_keys(definition),
+            aggregates,
+            uses_parameters: PlainCelTest::uses_parameters(&program),
+            variables,
             program,
         })
     }
@@ -156,15 +464,27 @@ impl PlainColumnCheck for PlainCelTest {
         &self,
         value: &str,
         data_type: &sqlparser::ast::DataType,
-        _lookup_table: &mut HashMap<String, HashSet<String>>,
+        lookup_table: &mut LookupStore,
     ) -> Result<bool, anyhow::Error> {
-        let context = self.build_context(self.get_column_name(), value, data_type)?;
-        match self.program.execute(&context)? {
-            cel_interpreter::objects::Value::Bool(v) => {
+        let value_per_field = ValuesMap::from([(self.column_name.to_owned(), (value.to_owned(), data_type.to_owned()))]);
+        self.test_row(&value_per_field, lookup_table)
+    }
+
+    fn test_row(
+        &self,
+        value_per_field: &ValuesMap,
+        lookup_table: &mut LookupStore,
+    ) -> Result<bool, anyhow::Error> {
+        let context = self.build_context(value_per_field, lookup_table)?;
+        match self.program.execute(&context) {
+            Ok(CelValue::Bool(v)) => {
                 // println!("testing {}.{} {} -> {}", self.table, self.column, &other_value, &v);
                 Ok(v)
             }
-            _ => panic!("filter does not return a boolean"),
+            Ok(_) => panic!("filter does not return a boolean"),
+            // the max or min of a group without rows is null, and like NULL in SQL it fails any comparison
+            Err(ExecutionError::ValuesNotComparable(left, right)) if left == CelValue::Null || right == CelValue::Null => Ok(false),
+            Err(e) => Err(e.into()),
         }
     }
 
@@ -205,9 +525,27 @@ pub struct PlainLookupTest {
     column_key: String,
     definition: String,
     target_column_key: String,
+    policy: LookupPolicy,
 }
 
 impl PlainLookupTest {
+    fn from_cascade(definition: &str, table: &str, policy: LookupPolicy) -> Result<Self, anyhow::Error> {
+        let mut split = definition.split("->");
+        let (Some(source_column), Some(foreign_key), None) = (split.next(), split.next(), split.next()) else {
+            panic!("cannot parse cascade");
+        };
+
+        Ok(PlainLookupTest {
+            key: String::from("lookup: ") + table + ": " + definition,
+            table_name: table.to_owned(),
+            column_name: source_column.to_owned(),
+            column_key: String::from(table) + "." + source_column,
+            definition: definition.to_owned(),
+            target_column_key: foreign_key.to_owned(),
+            policy,
+        })
+    }
+
     pub fn get_column_info(definition: &str) -> Result<(String, Vec<String>), anyhow::Error> {
         let mut split = definition.split("->");
         let (Some(column_name), Some(foreign_key), None) = (split.next(), split.next(), split.next()) else {
@@ -219,29 +557,246 @@ impl PlainLookupTest {
 
 impl PlainColumnCheck for PlainLookupTest {
     fn new(definition: &str, table: &str) -> Result<impl PlainColumnCheck + 'static, anyhow::Error> where Self: Sized {
-        let mut split = definition.split("->");
-        let (Some(source_column), Some(foreign_key), None) = (split.next(), split.next(), split.next()) else {
-            panic!("cannot parse cascade");
+        PlainLookupTest::from_cascade(definition, table, LookupPolicy::default())
+    }
+
+    fn test_value(
+        &self,
+        value: &str,
+        data_type: &sqlparser::ast::DataType,
+        lookup_table: &mut LookupStore,
+    ) -> Result<bool, anyhow::Error> {
+        // an optional parent that is not set cannot dangle
+        if value == "NULL" {
+            return Ok(!self.policy.drop_null);
+        }
+
+        if !lookup_table.has(&self.target_column_key) {
+            if self.policy.strict || self.policy.on_missing == OnMissing::Error || self.target_column_key.starts_with(FILE_KEY_PREFIX) {
+                return Err(anyhow::anyhow!("{} was never tracked, cannot check {}", self.target_column_key, self.column_key));
+            }
+            if !self.policy.required {
+                return Ok(true);
+            }
+        } else if lookup_table.contains(&self.target_column_key, value, data_type)? {
+            return Ok(true);
+        }
+
+        match self.policy.on_missing {
+            OnMissing::Keep => Ok(true),
+            OnMissing::Error => Err(anyhow::anyhow!("dangling {} = {} has no match in {}", self.column_key, value, self.target_column_key)),
+            OnMissing::Drop | OnMissing::SetNull => Ok(false),
+        }
+    }
+
+    fn get_key(&self) -> &str {
+        &self.key
+    }
+
+    fn get_definition(&self) -> &str {
+        &self.definition
+    }
+
+    fn get_table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    fn get_column_name(&self) -> &str {
+        &self.column_name
+    }
+
+    fn get_column_key(&self) -> &str {
+        &self.column_key
+    }
+
+    fn get_tracked_columns(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct PlainPolymorphicTest {
+    key: String,
+    table_name: String,
+    column_name: String,
+    column_key: String,
+    type_column_name: String,
+    lookups: HashMap<String, PlainLookupTest>,
+    policy: LookupPolicy,
+}
+
+impl PlainPolymorphicTest {
+    fn from_targets(
+        column: &str,
+        type_column: &str,
+        targets: &HashMap<String, String>,
+        table: &str,
+        policy: LookupPolicy,
+    ) -> Result<Self, anyhow::Error> {
+        let lookups = targets.iter().map(|(type_value, target_key)| {
+            let definition = String::from(column) + "->" + target_key;
+            Ok((type_value.to_owned(), PlainLookupTest::from_cascade(&definition, table, policy)?))
+        }).collect::<Result<HashMap<String, PlainLookupTest>, anyhow::Error>>()?;
+
+        Ok(PlainPolymorphicTest {
+            key: String::from("polymorphic: ") + table + ": " + type_column + ":" + column,
+            table_name: table.to_owned(),
+            column_name: column.to_owned(),
+            column_key: String::from(table) + "." + column,
+            type_column_name: type_column.to_owned(),
+            lookups,
+            policy,
+        })
+    }
+}
+
+impl PlainColumnCheck for PlainPolymorphicTest {
+    fn new(_definition: &str, _table: &str) -> Result<impl PlainColumnCheck + 'static, anyhow::Error> where Self: Sized {
+        Err::<PlainPolymorphicTest, anyhow::Error>(anyhow::anyhow!("polymorphic cascades cannot be expressed as a single definition"))
+    }
+
+    fn test_value(
+        &self,
+        _value: &str,
+        _data_type: &sqlparser::ast::DataType,
+        _lookup_table: &mut LookupStore,
+    ) -> Result<bool, anyhow::Error> {
+        Err(anyhow::anyhow!("{} needs the {} column to be tested", self.key, self.type_column_name))
+    }
+
+    fn test_row(
+        &self,
+        value_per_field: &ValuesMap,
+        lookup_table: &mut LookupStore,
+    ) -> Result<bool, anyhow::Error> {
+        let (type_value, _) = &value_per_field[&self.type_column_name];
+        let (str_value, data_type) = &value_per_field[&self.column_name];
+
+        match self.lookups.get(&Value::parse_string(type_value)) {
+            Some(lookup) => lookup.test_value(str_value, data_type, lookup_table),
+            // the association points to a type nobody tracks
+            None if self.policy.strict => Err(anyhow::anyhow!("{} has no target for type {}", self.key, type_value)),
+            None => Ok(true),
+        }
+    }
+
+    fn get_key(&self) -> &str {
+        &self.key
+    }
+
+    fn get_definition(&self) -> &str {
+        &self.column_name
+    }
+
+    fn get_table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    fn get_column_name(&self) -> &str {
+        &self.column_name
+    }
+
+    fn get_column_key(&self) -> &str {
+        &self.column_key
+    }
+
+    fn get_tracked_columns(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Debug)]
+enum GroupOperator {
+    Any,
+    All,
+    Not,
+}
+
+#[derive(Debug)]
+pub struct PlainGroupTest {
+    key: String,
+    table_name: String,
+    column_name: String,
+    column_key: String,
+    operator: GroupOperator,
+    checks: Vec<PlainCheckType>,
+}
+
+impl PlainGroupTest {
+    fn from_group(group: &RuleGroup, table: &str, strict: bool) -> Result<Self, anyhow::Error> {
+        let (operator, label) = match group {
+            RuleGroup::Any { .. } => (GroupOperator::Any, "any"),
+            RuleGroup::All { .. } => (GroupOperator::All, "all"),
+            RuleGroup::Not { .. } => (GroupOperator::Not, "not"),
+            RuleGroup::Named { rule_set } => return Err(anyhow::anyhow!("rule set {rule_set} was not resolved")),
+        };
+        let checks = group.branches().into_iter()
+            .map(|condition| new_plain_test(table, condition, strict))
+            .collect::<Result<Vec<PlainCheckType>, anyhow::Error>>()?;
+        let Some(first) = checks.first() else {
+            return Err(anyhow::anyhow!("{label} group on {table} has no rules"));
         };
+        let column_name = first.get_column_name().to_owned();
+        let keys: Vec<&str> = checks.iter().map(|check| check.get_key()).collect();
 
-        Ok(PlainLookupTest {
-            key: String::from("lookup: ") + table + ": " + definition,
+        Ok(PlainGroupTest {
+            key: String::from(label) + ": " + table + ": [" + &keys.join(", ") + "]",
             table_name: table.to_owned(),
-            column_name: source_column.to_owned(),
-            column_key: String::from(table) + "." + source_column,
-            definition: definition.to_owned(),
-            target_column_key: foreign_key.to_owned(),
+            column_key: String::from(table) + "." + &column_name,
+            column_name,
+            operator,
+            checks,
         })
     }
+}
+
+impl PlainColumnCheck for PlainGroupTest {
+    fn new(_definition: &str, _table: &str) -> Result<impl PlainColumnCheck + 'static, anyhow::Error> where Self: Sized {
+        Err::<PlainGroupTest, anyhow::Error>(anyhow::anyhow!("rule groups cannot be expressed as a single definition"))
+    }
 
     fn test_value(
         &self,
-        value: &str,
+        _value: &str,
         _data_type: &sqlparser::ast::DataType,
-        lookup_table: &mut HashMap<String, HashSet<String>>,
+        _lookup_table: &mut LookupStore,
+    ) -> Result<bool, anyhow::Error> {
+        Err(anyhow::anyhow!("{} needs the whole row to be tested", self.key))
+    }
+
+    fn test_row(
+        &self,
+        value_per_field: &ValuesMap,
+        lookup_table: &mut LookupStore,
     ) -> Result<bool, anyhow::Error> {
-        let Some(set) = lookup_table.get(&self.target_column_key) else { return Ok(true) };
-        Ok(set.contains(value))
+        // branches are evaluated lazily, like the checks of a table
+        match self.operator {
+            GroupOperator::Any => {
+                for check in self.checks.iter() {
+                    if check.test_row(value_per_field, lookup_table)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            },
+            GroupOperator::All => {
+                for check in self.checks.iter() {
+                    if !check.test_row(value_per_field, lookup_table)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            },
+            GroupOperator::Not => Ok(!self.checks[0].test_row(value_per_field, lookup_table)?),
+        }
     }
 
     fn get_key(&self) -> &str {
@@ -249,7 +804,7 @@ impl PlainColumnCheck for PlainLookupTest {
     }
 
     fn get_definition(&self) -> &str {
-        &self.definition
+        &self.key
     }
 
     fn get_table_name(&self) -> &str {
@@ -305,14 +860,14 @@ impl PlainColumnCheck for PlainTrackingTest {
     fn test_value(
         &self,
         value: &str,
-        _data_type: &sqlparser::ast::DataType,
-        lookup_table: &mut HashMap<String, HashSet<String>>,
+        data_type: &sqlparser::ast::DataType,
+        lookup_table: &mut LookupStore,
     ) -> Result<bool, anyhow::Error> {
-        let key = self.get_column_key();
-        match lookup_table.get_mut(key) {
-            None => { lookup_table.insert(self.get_column_key().to_owned(), HashSet::from([value.to_owned()])); }
-            Some(values) => { values.insert(value.to_owned()); }
+        if value == "NULL" {
+            return Ok(true);
         }
+
+        lookup_table.insert(self.get_column_key(), value, data_type)?;
         Ok(true)
     }
 
@@ -346,29 +901,47 @@ impl PlainColumnCheck for PlainTrackingTest {
 }
 
 #[derive(Debug)]
-pub struct TableChecks { checks: Vec<PlainCheckType>, text_transforms: HashMap<String, String> }
+pub struct TableChecks { checks: Vec<PlainCheckType>, pulls: Vec<PlainCheckType>, text_transforms: ColumnTransforms }
 
 impl TableChecks {
-    pub fn new(mut checks: Vec<PlainCheckType>, text_transforms: Option<&HashMap<String, String>>) -> Self {
+    pub fn new(table: &str, checks: Vec<PlainCheckType>, text_transforms: Option<&HashMap<String, Transform>>) -> Result<Self, anyhow::Error> {
+        let (pulls, mut checks): (Vec<PlainCheckType>, Vec<PlainCheckType>) = checks.into_iter()
+            .partition(|check| check.as_any().downcast_ref::<PlainLookupTest>().is_some_and(|l| l.policy.pull));
         // tests have implicit order
         checks.sort_by_key(|a| {
-            if a.as_any().downcast_ref::<PlainTrackingTest>().is_some() {
-                return true;
-            }
-            false
+            // tracking and aggregating only see the rows that are kept
+            TableChecks::is_bookkeeping(a)
         });
-        Self { checks, text_transforms: text_transforms.map(|x| x.iter().map(
-            |(f, v)| (f.to_owned(), format!("'{v}'")),
-        ).collect()).unwrap_or_default() }
+        Ok(Self { checks, pulls, text_transforms: ColumnTransforms::new(table, text_transforms)? })
+    }
+
+    fn is_bookkeeping(check: &PlainCheckType) -> bool {
+        check.as_any().downcast_ref::<PlainTrackingTest>().is_some() || check.as_any().downcast_ref::<PlainAggregateTest>().is_some()
+    }
+
+    fn is_pulled(&self, value_per_field: &ValuesMap, lookup_table: &mut LookupStore) -> Result<bool, anyhow::Error> {
+        for pull in self.pulls.iter() {
+            if pull.test_row(value_per_field, lookup_table)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // the keys of a table are tracked from its pass on, tables without any rows included
+    pub fn start_tracking(&self, lookup_table: &mut LookupStore) {
+        for key in self.checks.iter().flat_map(|check| check.get_tracked_columns()) {
+            lookup_table.start_tracking(key);
+        }
     }
 
     pub fn apply<'a, T>(
         &'a self,
         mut statement: T,
-        lookup_table: &'a mut HashMap<String, HashSet<String>>,
+        lookup_table: &'a mut LookupStore,
     ) -> Result<Option<T>, anyhow::Error>
         where
-            T: IntoIterator + Clone + Extend<(&'a String, &'a String)> + std::fmt::Debug,
+            T: IntoIterator + Clone + for<'b> Extend<(&'b String, &'b String)> + std::fmt::Debug,
             HashMap<String, (String, sqlparser::ast::DataType)>: FromIterator<<T>::Item>
     {
         let value_per_field: HashMap<String, (String, sqlparser::ast::DataType)> = statement.clone().into_iter().collect();
@@ -377,49 +950,87 @@ impl TableChecks {
             return Ok(Some(statement));
         }
 
+        // a row referenced by a surviving join row is kept as it is, only its keys are tracked
+        let pulled = self.is_pulled(&value_per_field, lookup_table)?;
+        // without checks of its own, a pulled side keeps only the rows that are referenced
+        if !pulled && !self.pulls.is_empty() && self.checks.iter().all(TableChecks::is_bookkeeping) {
+            return Ok(None);
+        }
+        let mut nullified: Vec<&'a String> = Vec::new();
         for check in self.checks.iter() {
-            let col_name = check.get_column_name();
-            let (str_value, data_type): &(String, sqlparser::ast::DataType) = &value_per_field[col_name];
-            if !check.test_value(str_value, data_type, lookup_table)? {
-                return Ok(None);
+            if pulled && !TableChecks::is_bookkeeping(check) {
+                continue;
+            }
+            if !check.test_row(&value_per_field, lookup_table)? {
+                let lookup = check.as_any().downcast_ref::<PlainLookupTest>().map(|l| (&l.column_name, l.policy))
+                    .or_else(|| check.as_any().downcast_ref::<PlainPolymorphicTest>().map(|p| (&p.column_name, p.policy)));
+                match lookup {
+                    Some((column, policy)) if policy.on_missing == OnMissing::SetNull => nullified.push(column),
+                    _ => return Ok(None),
+                }
             }
         }
 
-        statement.extend(self.text_transforms.iter());
+        statement.extend(nullified.into_iter().map(|column| (column, &*NULL)));
+        let transformed = self.text_transforms.apply(&value_per_field, lookup_table)?;
+        statement.extend(transformed.iter().map(|(column, value)| (column, value)));
         Ok(Some(statement))
     }
 }
 
 type PassChecks = HashMap<String, TableChecks>;
 
+// aggregates are computed by a read-only pre-pass over the tables they group,
+// the passes filter and rewrite the tables in dependency order
 #[derive(Debug)]
-pub struct DBChecks(pub Vec<PassChecks>);
+pub struct DBChecks {
+    pub pre_pass: PassChecks,
+    pub passes: Vec<PassChecks>,
+}
 
 impl DBChecks {
-    fn new(items: Vec<Vec<Vec<PlainCheckType>>>, text_transforms: HashMap<String, HashMap<String, String>>) -> Self {
-        Self(items.into_iter().map(|t_items| {
+    fn new(
+        items: Vec<Vec<Vec<PlainCheckType>>>,
+        aggregates: Aggregates,
+        text_transforms: HashMap<String, HashMap<String, Transform>>,
+    ) -> Result<Self, anyhow::Error> {
+        let pre_pass = aggregates.into_iter().map(|(table_name, it)| {
+            Ok((table_name.to_owned(), TableChecks::new(&table_name, it, None)?))
+        }).collect::<Result<PassChecks, anyhow::Error>>()?;
+
+        let mut passes = items.into_iter().map(|t_items| {
             t_items.into_iter().map(|it| {
                 let table_name = it[0].get_table_name().to_owned();
-                (table_name.to_string(), TableChecks::new(it, text_transforms.get(&table_name)))
-            }).collect()
-        }).collect())
-    }
-}
-
-impl IntoIterator for DBChecks {
-    type Item = PassChecks;
-    type IntoIter = std::vec::IntoIter<Self::Item>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        self.0.into_iter()
+                Ok((table_name.to_string(), TableChecks::new(&table_name, it, text_transforms.get(&table_name))?))
+            }).collect::<Result<PassChecks, anyhow::Error>>()
+        }).collect::<Result<Vec<PassChecks>, anyhow::Error>>()?;
+
+        // tables that are only transformed nobody depends on, the last pass takes them
+        let mut transformed_only: Vec<(&String, &HashMap<String, Transform>)> = text_transforms.iter()
+            .filter(|(table, _)| !passes.iter().any(|pass| pass.contains_key(*table)))
+            .collect();
+        transformed_only.sort_by_key(|(table, _)| *table);
+        if !transformed_only.is_empty() && passes.is_empty() {
+            passes.push(PassChecks::new());
+        }
+        for (table, transforms) in transformed_only {
+            let last = passes.last_mut().unwrap();
+            last.insert(table.to_owned(), TableChecks::new(table, Vec::new(), Some(transforms))?);
+        }
+        Ok(Self { pre_pass, passes })
     }
 }
 
-fn new_plain_test(table: &str, definition: &str) -> Result<PlainCheckType, anyhow::Error> {
-    let item: PlainCheckType = if definition.contains("->") {
-        Box::new(PlainLookupTest::new(definition, table)?)
-    } else {
-        Box::new(PlainCelTest::new(definition, table)?)
+fn new_plain_test(table: &str, condition: &Condition, strict: bool) -> Result<PlainCheckType, anyhow::Error> {
+    let item: PlainCheckType = match condition {
+        Condition::Group(group) => Box::new(PlainGroupTest::from_group(group, table, strict)?),
+        Condition::Polymorphic { column, type_column, targets, .. } => {
+            Box::new(PlainPolymorphicTest::from_targets(column, type_column, targets, table, condition.policy(strict))?)
+        },
+        Condition::Plain(definition) | Condition::Detailed { definition, .. } if definition.contains("->") => {
+            Box::new(PlainLookupTest::from_cascade(definition, table, condition.policy(strict))?)
+        },
+        Condition::Plain(definition) | Condition::Detailed { definition, .. } => Box::new(PlainCelTest::new(definition, table)?),
     };
     Ok(item)
 }
@@ -429,7 +1040,20 @@ fn new_tracking_test(table: &str, definition: &str) -> Result<PlainCheckType, an
 }
 
 
-fn determine_foreign_keys(definition: &str) -> Result<Vec<String>, anyhow::Error> {
+fn determine_foreign_keys(condition: &Condition) -> Result<Vec<String>, anyhow::Error> {
+    let definition = match condition {
+        Condition::Group(group) => {
+            // every branch may be the one keeping the row, so all of them are dependencies
+            let mut foreign_keys = Vec::new();
+            for branch in group.branches() {
+                foreign_keys.extend(determine_foreign_keys(branch)?);
+            }
+            return Ok(foreign_keys);
+        },
+        Condition::Polymorphic { targets, .. } => return Ok(targets.values().cloned().collect()),
+        Condition::Plain(definition) | Condition::Detailed { definition, .. } => definition,
+    };
+
     let (_, foreign_keys) = if definition.contains("->") {
         PlainLookupTest::get_column_info(definition)?
     } else {
@@ -438,7 +1062,17 @@ fn determine_foreign_keys(definition: &str) -> Result<Vec<String>, anyhow::Error
     Ok(foreign_keys)
 }
 
-fn split_column_key(key: &str) -> Result<(&str, &str), anyhow::Error> {
+pub fn resolve_rule_sets(
+    conditions: &mut HashMap<String, Vec<Condition>>,
+    rule_sets: &HashMap<String, Vec<Condition>>,
+) -> Result<(), anyhow::Error> {
+    for condition in conditions.values_mut().flatten() {
+        condition.resolve(rule_sets, &mut Vec::new())?;
+    }
+    Ok(())
+}
+
+pub fn split_column_key(key: &str) -> Result<(&str, &str), anyhow::Error> {
     let mut split = key.split('.');
     let (Some(table), Some(column), None) = (split.next(), split.next(), split.next()) else {
         return Err(anyhow::anyhow!("malformed key {}", key));
@@ -446,28 +1080,150 @@ fn split_column_key(key: &str) -> Result<(&str, &str), anyhow::Error> {
     Ok((table, column))
 }
 
-pub fn get_passes<'a, I: Iterator<Item=(&'a String, &'a Vec<String>)>>(conditions: I, text_transforms: HashMap<String, HashMap<String, String>>) -> Result<DBChecks, anyhow::Error> {
-    let definitions: Vec<(String, String)> = conditions.flat_map(|(table, conds)| {
-        conds.iter().map(|c| (table.to_owned(), c.to_owned()))
-    }).collect();
-
-    let mut root = DependencyNode::<PlainCheckType>::new();
-    for (source_table, definition) in definitions.iter() {
-        root.add_child_to_group(new_plain_test(source_table, definition)?, source_table)?;
+// aggregating tests per grouped table, run before any table is filtered
+type Aggregates = BTreeMap<String, Vec<PlainCheckType>>;
+
+// adds the test of a condition along with the tracking and aggregating tests it relies on,
+// returns the tables that have to be processed first
+fn add_dependencies(
+    root: &mut DependencyNode<PlainCheckType>,
+    aggregates: &mut Aggregates,
+    source_table: &str,
+    condition: &Condition,
+    strict: bool,
+) -> Result<Vec<String>, anyhow::Error> {
+    root.add_child_to_group(new_plain_test(source_table, condition, strict)?, source_table)?;
+
+    let mut parents: Vec<String> = Vec::new();
+    // key files are loaded upfront and need no pass of their own
+    for target_key in determine_foreign_keys(condition)?.into_iter().filter(|key| !key.starts_with(FILE_KEY_PREFIX)) {
+        let (target_table, _) = split_column_key(&target_key)?;
+        root.add_child_to_group(new_tracking_test(target_table, &target_key)?, target_table)?;
+        parents.push(target_table.to_owned());
+    }
 
-        for target_key in determine_foreign_keys(definition)? {
-            let (target_table, _) = split_column_key(&target_key)?;
+    for definition in condition.definitions() {
+        for reference in AggregateReference::find_all(definition)? {
+            let aggregate = PlainAggregateTest::from_reference(&reference)?;
+            let table = aggregate.get_table_name().to_owned();
+            if reference.kept {
+                // kept rows are only known once the pass of the grouped table is over
+                root.add_child_to_group(Box::new(aggregate), &table)?;
+                parents.push(table);
+                continue;
+            }
+            // the pre-pass sees every row of the grouped table, so these aggregates order no tables
+            let table_aggregates = aggregates.entry(table).or_default();
+            if !table_aggregates.iter().any(|a| a.get_key() == aggregate.get_key()) {
+                table_aggregates.push(Box::new(aggregate));
+            }
+        }
+    }
+    Ok(parents)
+}
 
-            let target_check = new_tracking_test(target_table, &target_key)?;
-            root.add_child_to_group(target_check, target_table)?;
+// parents of every table, collected over all of its conditions before any table is moved
+type Parents = BTreeMap<String, Vec<String>>;
+
+fn add_join_table(
+    root: &mut DependencyNode<PlainCheckType>,
+    join_table: &str,
+    join: &JoinTable,
+    strict: bool,
+    parents: &mut Parents,
+) -> Result<(), anyhow::Error> {
+    // a join row survives only if every side it points to survived
+    let policy = LookupPolicy { strict, required: true, ..Default::default() };
+    // a pulled side has rows no join row references, they are left to its own checks
+    let pull_policy = LookupPolicy { required: true, pull: true, ..Default::default() };
+    let pulled = join.pull.as_deref().unwrap_or_default();
+
+    for side in join.sides.iter() {
+        let (column, foreign_keys) = PlainLookupTest::get_column_info(side)?;
+        let target_key = &foreign_keys[0];
+        let (target_table, target_column) = split_column_key(target_key)?;
+
+        if pulled.iter().any(|p| p == target_table) {
+            // cascade the other way: the side keeps the rows referenced by surviving join rows too
+            let join_key = String::from(join_table) + "." + &column;
+            root.add_child_to_group(new_tracking_test(join_table, &join_key)?, join_table)?;
+            let definition = String::from(target_column) + "->" + &join_key;
+            root.add_child_to_group(Box::new(PlainLookupTest::from_cascade(&definition, target_table, pull_policy)?), target_table)?;
+            parents.entry(target_table.to_owned()).or_default().push(join_table.to_owned());
+        } else {
+            root.add_child_to_group(Box::new(PlainLookupTest::from_cascade(side, join_table, policy)?), join_table)?;
+            root.add_child_to_group(new_tracking_test(target_table, target_key)?, target_table)?;
+            parents.entry(join_table.to_owned()).or_default().push(target_table.to_owned());
+        }
+    }
+    Ok(())
+}
 
-            root.move_under(target_table, source_table)?;
+// Moves every table under the deepest of its parents, which places it after all of them.
+// A table takes the ones below it along, so moving it can leave a child above one of its
+// other parents; the tables are moved again until none of them is.
+fn schedule(root: &mut DependencyNode<PlainCheckType>, parents: &Parents) -> Result<(), anyhow::Error> {
+    for _ in 0..=parents.len() {
+        let mut moved = false;
+        for (table, table_parents) in parents.iter() {
+            if table_parents.contains(table) {
+                return Err(anyhow::anyhow!("{table} cannot depend on itself"));
+            }
+            let Some(deepest) = table_parents.iter().max_by_key(|parent| root.depth_of(parent)) else { continue };
+            if root.depth_of(deepest) < root.depth_of(table) {
+                continue;
+            }
+            if root.is_under(table, deepest) {
+                return Err(anyhow::anyhow!("{table} and {deepest} depend on each other"));
+            }
+            root.move_under(deepest, table)?;
+            moved = true;
+        }
+        if !moved {
+            return Ok(());
         }
     }
+    Err(anyhow::anyhow!("cannot order the tables into passes"))
+}
+
+fn plan_passes<'a, I: Iterator<Item=(&'a String, &'a Vec<Condition>)>>(
+    conditions: I,
+    join_tables: &HashMap<String, JoinTable>,
+    strict: bool,
+) -> Result<(DependencyNode<PlainCheckType>, Aggregates), anyhow::Error> {
+    let mut root = DependencyNode::<PlainCheckType>::new();
+    let mut aggregates = Aggregates::new();
+    let mut parents = Parents::new();
+    for (source_table, condition) in conditions.flat_map(|(table, conds)| conds.iter().map(move |c| (table, c))) {
+        // a CEL filter may reference several tracked keys, a table several parents
+        let condition_parents = add_dependencies(&mut root, &mut aggregates, source_table, condition, strict)?;
+        parents.entry(source_table.to_owned()).or_default().extend(condition_parents);
+    }
 
+    for (join_table, join) in join_tables.iter() {
+        add_join_table(&mut root, join_table, join, strict, &mut parents)?;
+    }
+
+    schedule(&mut root, &parents)?;
+    Ok((root, aggregates))
+}
 
-    let db_checks = DBChecks::new(chunk_by_depth(root), text_transforms);
-    dbg!(&db_checks);
+pub fn get_passes<'a, I: Iterator<Item=(&'a String, &'a Vec<Condition>)>>(
+    conditions: I,
+    join_tables: &HashMap<String, JoinTable>,
+    text_transforms: HashMap<String, HashMap<String, Transform>>,
+    strict: bool,
+) -> Result<DBChecks, anyhow::Error> {
+    let (root, aggregates) = plan_passes(conditions, join_tables, strict)?;
+    DBChecks::new(chunk_by_depth(root), aggregates, text_transforms)
+}
 
-    Ok(db_checks)
+// tables in the order the passes process them, parents before the tables depending on them
+pub fn get_table_order<'a, I: Iterator<Item=(&'a String, &'a Vec<Condition>)>>(
+    conditions: I,
+    join_tables: &HashMap<String, JoinTable>,
+) -> Result<Vec<String>, anyhow::Error> {
+    let (root, _) = plan_passes(conditions, join_tables, false)?;
+    let passes = chunk_by_depth(root);
+    Ok(passes.iter().flatten().filter_map(|checks| checks.first()).map(|check| check.get_table_name().to_owned()).collect())
 }
@@ -0,0 +1,141 @@
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::checks::Condition;
+
+// keys like `*`, `audit_*` or `/^log_\d+$/` stand for every table they match
+pub fn is_table_pattern(key: &str) -> bool {
+    key.contains(['*', '?', '[']) || (key.len() > 1 && key.starts_with('/') && key.ends_with('/'))
+}
+
+fn compile_pattern(pattern: &str) -> Result<Regex, anyhow::Error> {
+    if let Some(expression) = pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+        return Ok(Regex::new(expression)?);
+    }
+
+    let mut expression = String::from("^");
+    let mut in_class = false;
+    for c in pattern.chars() {
+        match c {
+            '*' if !in_class => expression.push_str(".*"),
+            '?' if !in_class => expression.push('.'),
+            '[' if !in_class => {
+                in_class = true;
+                expression.push('[');
+            },
+            ']' if in_class => {
+                in_class = false;
+                expression.push(']');
+            },
+            '!' if in_class && expression.ends_with('[') => expression.push('^'),
+            // a range like `[0-9]`
+            '-' if in_class && !expression.ends_with(['[', '^']) => expression.push('-'),
+            _ => expression.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    expression.push('$');
+    Ok(Regex::new(&expression)?)
+}
+
+pub fn condition_columns(conditions: &[Condition]) -> Result<Vec<String>, anyhow::Error> {
+    let mut columns = Vec::new();
+    for condition in conditions.iter() {
+        columns.extend(condition.columns()?);
+    }
+    Ok(columns)
+}
+
+// Replaces pattern keys with the tables that match them and have every column the entry
+// refers to. Entries of explicitly named tables are applied after the expanded ones.
+pub fn expand_table_patterns<V, F>(
+    entries: HashMap<String, V>,
+    table_columns: &HashMap<String, HashSet<String>>,
+    referenced_columns: F,
+) -> Result<HashMap<String, V>, anyhow::Error>
+    where
+        V: Clone + Default + IntoIterator + Extend<<V as IntoIterator>::Item>,
+        F: Fn(&V) -> Result<Vec<String>, anyhow::Error>,
+{
+    let mut patterns: BTreeMap<String, V> = BTreeMap::new();
+    let mut named: HashMap<String, V> = HashMap::new();
+    for (key, value) in entries.into_iter() {
+        if is_table_pattern(&key) {
+            patterns.insert(key, value);
+        } else {
+            named.insert(key, value);
+        }
+    }
+
+    let mut expanded: HashMap<String, V> = HashMap::new();
+    for (pattern, value) in patterns.iter() {
+        let regex = compile_pattern(pattern)?;
+        let columns = referenced_columns(value)?;
+        let mut matched: Vec<&String> = table_columns.iter()
+            .filter(|(table, table_columns)| regex.is_match(table) && columns.iter().all(|c| table_columns.contains(c)))
+            .map(|(table, _)| table)
+            .collect();
+        matched.sort();
+        println!("Table pattern {pattern} applies to {} tables", matched.len());
+        for table in matched {
+            expanded.entry(table.to_owned()).or_default().extend(value.clone());
+        }
+    }
+
+    for (table, value) in named.into_iter() {
+        expanded.entry(table).or_default().extend(value);
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, table: &str) -> bool {
+        compile_pattern(pattern).unwrap().is_match(table)
+    }
+
+    #[test]
+    fn globs_match_whole_names() {
+        assert!(matches("*", "users"));
+        assert!(matches("audit_*", "audit_log"));
+        assert!(matches("audit_*", "audit_"));
+        assert!(!matches("audit_*", "old_audit_log"));
+        assert!(matches("log_?", "log_1"));
+        assert!(!matches("log_?", "log_12"));
+        // regex characters are taken literally
+        assert!(matches("a.b", "a.b"));
+        assert!(!matches("a.b", "axb"));
+        assert!(matches("log$", "log$"));
+    }
+
+    #[test]
+    fn glob_classes() {
+        assert!(matches("log_[12]", "log_1"));
+        assert!(!matches("log_[12]", "log_3"));
+        assert!(matches("log_[0-9]", "log_7"));
+        assert!(!matches("log_[0-9]", "log_-"));
+        assert!(matches("log_[!0-9]", "log_x"));
+        assert!(!matches("log_[!0-9]", "log_7"));
+        assert!(matches("log_[!-]", "log_a"));
+        assert!(!matches("log_[!-]", "log_-"));
+        assert!(matches("log_[-a]", "log_-"));
+        // `!` is only negating in the first position
+        assert!(matches("log_[a!]", "log_!"));
+        assert!(matches("log_[*?]", "log_*"));
+        assert!(!matches("log_[*?]", "log_a"));
+    }
+
+    #[test]
+    fn slashes_delimit_regular_expressions() {
+        assert!(is_table_pattern("/^log_\\d+$/"));
+        assert!(!is_table_pattern("/"));
+        assert!(!is_table_pattern("users"));
+        assert!(matches("/^log_\\d+$/", "log_42"));
+        assert!(!matches("/^log_\\d+$/", "log_x"));
+        // not anchored unless the expression says so
+        assert!(matches("/audit/", "old_audit_log"));
+        assert!(compile_pattern("/(/").is_err());
+        assert!(compile_pattern("log_[").is_err());
+    }
+}
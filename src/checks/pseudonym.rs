@@ -0,0 +1,67 @@
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use sqlparser::ast::DataType;
+use std::path::PathBuf;
+
+// bytes of the HMAC rendered into text pseudonyms
+const TEXT_BYTES: usize = 12;
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct KeySource {
+    file: Option<PathBuf>,
+    env: Option<String>,
+}
+
+impl KeySource {
+    pub fn read(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let key = match (&self.file, &self.env) {
+            (Some(file), _) => std::fs::read_to_string(file)
+                .map_err(|e| anyhow::anyhow!("cannot read key from {}: {e}", file.display()))?,
+            (None, Some(env)) => std::env::var(env)
+                .map_err(|_| anyhow::anyhow!("environment variable {env} with the key is not set"))?,
+            (None, None) => return Err(anyhow::anyhow!("key needs a file or an env variable")),
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(anyhow::anyhow!("key is empty"));
+        }
+        Ok(key.as_bytes().to_vec())
+    }
+}
+
+fn hmac_sha256(key: &[u8], message: &[&[u8]]) -> [u8; 32] {
+    // HMAC takes keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    for part in message.iter() {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+// integer pseudonyms stay below this, so that INT and BIGINT columns of a namespace agree;
+// with about 2^31 pseudonyms two ids share one by the birthday bound at around 55k ids already
+pub const INTEGER_RANGE: u64 = i32::MAX as u64;
+
+pub fn is_wide_integer(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int(_) | DataType::IntUnsigned(_) | DataType::Integer(_) | DataType::IntegerUnsigned(_)
+        | DataType::BigInt(_) | DataType::BigIntUnsigned(_)
+    )
+}
+
+// Equal values get equal pseudonyms in every column of the namespace, integer columns
+// get integers so that foreign keys between pseudonymized columns still match.
+// Pseudonyms are not unique: different values may get the same one, integers much sooner
+// than text, so primary and unique keys are better renumbered or tokenized.
+pub fn pseudonymize(key: &[u8], namespace: &str, value: &str, data_type: &DataType) -> String {
+    let mac = hmac_sha256(key, &[namespace.as_bytes(), &[0], value.as_bytes()]);
+    if is_wide_integer(data_type) {
+        let bytes: [u8; 8] = mac[..8].try_into().unwrap();
+        // zero is avoided, it often means "no reference"
+        return ((u64::from_le_bytes(bytes) % INTEGER_RANGE) + 1).to_string();
+    }
+    mac[..TEXT_BYTES].iter().map(|b| format!("{b:02x}")).collect()
+}
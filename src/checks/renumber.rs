@@ -0,0 +1,197 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::checks::{Condition, FILE_KEY_PREFIX, JoinTable, PlainLookupTest, Value, split_column_key};
+use crate::lookup::{LookupStore, is_integer};
+
+type ValuesMap = HashMap<String, (String, sqlparser::ast::DataType)>;
+
+const DEFAULT_RENUMBER_COLUMN: &str = "id";
+const DEFAULT_START: i64 = 1;
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct RenumberConfig {
+    column: Option<String>,
+    start: Option<i64>,
+}
+
+// a column holding values of a renumbered key, polymorphic ones only in rows of a type
+#[derive(Debug, PartialEq)]
+struct Reference {
+    column: String,
+    key: String,
+    type_filter: Option<(String, String)>,
+}
+
+#[derive(Debug)]
+pub struct Renumbering {
+    // renumbered column and first id per table
+    keys: BTreeMap<String, (String, i64)>,
+    references: BTreeMap<String, Vec<Reference>>,
+}
+
+impl Renumbering {
+    pub fn new<'a>(
+        config: &HashMap<String, RenumberConfig>,
+        conditions: impl Iterator<Item = (&'a String, &'a Vec<Condition>)>,
+        join_tables: &HashMap<String, JoinTable>,
+        foreign_keys: &HashMap<String, Vec<(String, String)>>,
+    ) -> Result<Self, anyhow::Error> {
+        let keys: BTreeMap<String, (String, i64)> = config.iter().map(|(table, renumber)| {
+            let column = renumber.column.as_deref().unwrap_or(DEFAULT_RENUMBER_COLUMN).to_owned();
+            (table.to_owned(), (column, renumber.start.unwrap_or(DEFAULT_START)))
+        }).collect();
+        let mut renumbering = Renumbering { keys, references: BTreeMap::new() };
+
+        for (table, table_conditions) in conditions {
+            for condition in table_conditions.iter() {
+                renumbering.add_references(table, condition)?;
+            }
+        }
+        for (table, join) in join_tables.iter() {
+            for side in join.sides.iter() {
+                renumbering.add_reference(table, side, None)?;
+            }
+        }
+        // the dump declares references the config has no cascade for
+        for (table, table_foreign_keys) in foreign_keys.iter() {
+            for (column, key) in table_foreign_keys.iter() {
+                renumbering.add_reference(table, &(String::from(column) + "->" + key), None)?;
+            }
+        }
+
+        for (table, (column, _)) in renumbering.keys.iter() {
+            println!("Renumbering {table}.{column}, columns referencing it without a cascade, join table or foreign key keep the old ids");
+        }
+        Ok(renumbering)
+    }
+
+    fn add_references(&mut self, table: &str, condition: &Condition) -> Result<(), anyhow::Error> {
+        match condition {
+            Condition::Group(group) => {
+                for branch in group.branches() {
+                    self.add_references(table, branch)?;
+                }
+            },
+            Condition::Polymorphic { column, type_column, targets, .. } => {
+                for (type_value, target_key) in targets.iter() {
+                    let definition = String::from(column) + "->" + target_key;
+                    self.add_reference(table, &definition, Some((type_column.to_owned(), type_value.to_owned())))?;
+                }
+            },
+            Condition::Plain(definition) | Condition::Detailed { definition, .. } if definition.contains("->") => {
+                self.add_reference(table, definition, None)?;
+            },
+            Condition::Plain(_) | Condition::Detailed { .. } => {},
+        }
+        Ok(())
+    }
+
+    fn add_reference(&mut self, table: &str, definition: &str, type_filter: Option<(String, String)>) -> Result<(), anyhow::Error> {
+        let (column, foreign_keys) = PlainLookupTest::get_column_info(definition)?;
+        let key = &foreign_keys[0];
+        if key.starts_with(FILE_KEY_PREFIX) {
+            return Ok(());
+        }
+        let (target_table, target_column) = split_column_key(key)?;
+        if self.keys.get(target_table).is_none_or(|(renumbered, _)| renumbered != target_column) {
+            return Ok(());
+        }
+        // a key shared with the parent would be renumbered twice
+        if self.keys.get(table).is_some_and(|(renumbered, _)| *renumbered == column) {
+            return Err(anyhow::anyhow!("cannot renumber {table}.{column}, it references {key}"));
+        }
+
+        let reference = Reference { column, key: key.to_owned(), type_filter };
+        let references = self.references.entry(table.to_owned()).or_default();
+        if !references.contains(&reference) {
+            references.push(reference);
+        }
+        Ok(())
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = &String> {
+        self.keys.keys()
+    }
+
+    pub fn referencing_tables(&self) -> impl Iterator<Item = &String> {
+        self.references.keys()
+    }
+
+    // gives the rows of a table ids from the start on, in the order of the dump
+    pub fn number<T>(
+        &self,
+        table: &str,
+        mut statement: T,
+        lookup_table: &mut LookupStore,
+    ) -> Result<Option<T>, anyhow::Error>
+        where
+            T: IntoIterator + Clone + for<'b> Extend<(&'b String, &'b String)>,
+            ValuesMap: FromIterator<<T>::Item>
+    {
+        let value_per_field: ValuesMap = statement.clone().into_iter().collect();
+        let Some((column, start)) = self.keys.get(table) else {
+            return Err(anyhow::anyhow!("table {table} is not renumbered"));
+        };
+        if value_per_field.is_empty() {
+            return Ok(Some(statement));
+        }
+
+        let Some((str_value, data_type)) = value_per_field.get(column) else {
+            return Err(anyhow::anyhow!("cannot renumber unknown column {table}.{column}"));
+        };
+        if !is_integer(data_type) || str_value == "NULL" {
+            return Err(anyhow::anyhow!("cannot renumber {table}.{column}, only integer keys can be renumbered"));
+        }
+        let id = lookup_table.renumber(&(String::from(table) + "." + column), str_value, data_type, *start)?.to_string();
+        statement.extend([(column, &id)]);
+        Ok(Some(statement))
+    }
+
+    // rewrites the references to renumbered keys with the new ids
+    pub fn propagate<T>(
+        &self,
+        table: &str,
+        mut statement: T,
+        lookup_table: &LookupStore,
+    ) -> Result<Option<T>, anyhow::Error>
+        where
+            T: IntoIterator + Clone + for<'b> Extend<(&'b String, &'b String)>,
+            ValuesMap: FromIterator<<T>::Item>
+    {
+        let value_per_field: ValuesMap = statement.clone().into_iter().collect();
+        if value_per_field.is_empty() {
+            return Ok(Some(statement));
+        }
+
+        let mut ids = Vec::new();
+        for reference in self.references.get(table).into_iter().flatten() {
+            if let Some((type_column, type_value)) = &reference.type_filter
+                && value_per_field.get(type_column).is_none_or(|(value, _)| Value::parse_string(value) != *type_value)
+            {
+                continue;
+            }
+            let Some((str_value, data_type)) = value_per_field.get(&reference.column) else {
+                return Err(anyhow::anyhow!("cannot renumber unknown column {table}.{}", reference.column));
+            };
+            if str_value == "NULL" {
+                continue;
+            }
+            // an old id left in place would point to whichever row got it as its new id
+            let Some(id) = lookup_table.renumbered(&reference.key, str_value, data_type) else {
+                return Err(anyhow::anyhow!("{table}.{} = {str_value} references no kept row of {}, cannot renumber it", reference.column, reference.key));
+            };
+            ids.push((&reference.column, id.to_string()));
+        }
+        statement.extend(ids.iter().map(|(column, id)| (*column, id)));
+        Ok(Some(statement))
+    }
+
+    // the AUTO_INCREMENT every renumbered table continues with
+    pub fn next_ids(&self, lookup_table: &LookupStore) -> HashMap<String, i64> {
+        self.keys.iter().map(|(table, (column, start))| {
+            (table.to_owned(), lookup_table.next_id(&(String::from(table) + "." + column)).unwrap_or(*start))
+        }).collect()
+    }
+}
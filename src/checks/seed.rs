@@ -0,0 +1,208 @@
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::lookup::{LookupStore, normalize};
+use crate::checks::{Condition, FILE_KEY_PREFIX, JoinTable, PlainCelTest, PlainCheckType, PlainColumnCheck, PlainLookupTest, PlainTrackingTest, split_column_key};
+
+type ValuesMap = HashMap<String, (String, sqlparser::ast::DataType)>;
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct SeedConfig {
+    table: String,
+    filter: Option<String>,
+    ids: Option<Vec<String>>,
+    column: Option<String>,
+    depth: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Edge {
+    table: String,
+    column: String,
+    target_table: String,
+    target_column: String,
+}
+
+impl Edge {
+    fn from_cascade(table: &str, definition: &str) -> Result<Self, anyhow::Error> {
+        let (column, foreign_keys) = PlainLookupTest::get_column_info(definition)?;
+        let (target_table, target_column) = split_column_key(&foreign_keys[0])?;
+        Ok(Edge {
+            table: table.to_owned(),
+            column,
+            target_table: target_table.to_owned(),
+            target_column: target_column.to_owned(),
+        })
+    }
+}
+
+// a local column and the lookup key of the table on the other side of the edge
+#[derive(Debug)]
+struct Link {
+    column: String,
+    other_key: String,
+    // the other side is a child, so this table is the parent walked up to
+    to_parent: bool,
+}
+
+#[derive(Debug)]
+pub struct SeedTableChecks {
+    filter: Option<PlainCheckType>,
+    ids: Option<(String, HashSet<String>)>,
+    links: Vec<Link>,
+    tracking: Vec<PlainCheckType>,
+}
+
+impl SeedTableChecks {
+    fn new() -> Self {
+        Self { filter: None, ids: None, links: Vec::new(), tracking: Vec::new() }
+    }
+
+    fn add_link(&mut self, table: &str, column: &str, other_key: String, to_parent: bool) -> Result<(), anyhow::Error> {
+        let key = String::from(table) + "." + column;
+        if !self.tracking.iter().any(|t| t.get_column_key() == key) {
+            self.tracking.push(Box::new(PlainTrackingTest::new(&key, table)?));
+        }
+        self.links.push(Link { column: column.to_owned(), other_key, to_parent });
+        Ok(())
+    }
+
+    // A row is reached when it is a seed row or linked to a row reached before, through any
+    // edge; `parents_of` holds rows whose parents are reached as well, whatever the depth.
+    fn is_reached(
+        &self,
+        value_per_field: &ValuesMap,
+        previous: &LookupStore,
+        parents_of: Option<&LookupStore>,
+        lookup_table: &mut LookupStore,
+    ) -> Result<bool, anyhow::Error> {
+        if let Some(filter) = &self.filter && filter.test_row(value_per_field, lookup_table)? {
+            return Ok(true);
+        }
+
+        if let Some((column, ids)) = &self.ids {
+            let (str_value, data_type) = &value_per_field[column];
+            if ids.contains(normalize(str_value, data_type, false).as_ref()) {
+                return Ok(true);
+            }
+        }
+
+        for link in self.links.iter() {
+            let (str_value, data_type) = &value_per_field[&link.column];
+            if previous.contains(&link.other_key, str_value, data_type)? {
+                return Ok(true);
+            }
+            if link.to_parent && let Some(children) = parents_of && children.contains(&link.other_key, str_value, data_type)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn track<T>(
+        &self,
+        statement: T,
+        previous: &LookupStore,
+        parents_of: Option<&LookupStore>,
+        lookup_table: &mut LookupStore,
+    ) -> Result<Option<T>, anyhow::Error>
+        where
+            T: IntoIterator + Clone,
+            ValuesMap: FromIterator<<T>::Item>
+    {
+        let value_per_field: ValuesMap = statement.clone().into_iter().collect();
+
+        if !value_per_field.is_empty() && self.is_reached(&value_per_field, previous, parents_of, lookup_table)? {
+            for check in self.tracking.iter() {
+                let (str_value, data_type) = &value_per_field[check.get_column_name()];
+                check.test_value(str_value, data_type, lookup_table)?;
+            }
+        }
+
+        Ok(Some(statement))
+    }
+
+    pub fn apply<T>(
+        &self,
+        statement: T,
+        previous: &LookupStore,
+        parents_of: Option<&LookupStore>,
+    ) -> Result<Option<T>, anyhow::Error>
+        where
+            T: IntoIterator + Clone,
+            ValuesMap: FromIterator<<T>::Item>
+    {
+        let value_per_field: ValuesMap = statement.clone().into_iter().collect();
+
+        if value_per_field.is_empty() || self.is_reached(&value_per_field, previous, parents_of, &mut LookupStore::default())? {
+            return Ok(Some(statement));
+        }
+        Ok(None)
+    }
+}
+
+#[derive(Debug)]
+pub struct SeedWalk {
+    // in the order of the passes, so that every walk visits the tables alike
+    tables: Vec<(String, SeedTableChecks)>,
+    depth: Option<usize>,
+}
+
+impl SeedWalk {
+    pub fn new(
+        seed: &SeedConfig,
+        cascades: &HashMap<String, Vec<Condition>>,
+        join_tables: &HashMap<String, JoinTable>,
+        table_order: &[String],
+    ) -> Result<Self, anyhow::Error> {
+        let mut tables: HashMap<String, SeedTableChecks> = HashMap::new();
+
+        let mut seed_checks = SeedTableChecks::new();
+        if let Some(definition) = &seed.filter {
+            seed_checks.filter = Some(Box::new(PlainCelTest::new(definition, &seed.table)?));
+        }
+        if let Some(ids) = &seed.ids {
+            let column = seed.column.as_deref().unwrap_or("id");
+            // compared like tracked keys, so that `'042'` matches the id 42
+            let ids = ids.iter().map(|id| normalize(id, &sqlparser::ast::DataType::Text, false).into_owned()).collect();
+            seed_checks.ids = Some((column.to_owned(), ids));
+        }
+        if seed_checks.filter.is_none() && seed_checks.ids.is_none() {
+            return Err(anyhow::anyhow!("seed on {} needs a filter or a list of ids", seed.table));
+        }
+        tables.insert(seed.table.to_owned(), seed_checks);
+
+        let edges = cascades.iter()
+            .flat_map(|(table, conditions)| conditions.iter().flat_map(|c| c.definitions()).map(move |d| (table, d)))
+            .chain(join_tables.iter().flat_map(|(table, join)| join.sides.iter().map(move |s| (table, s.as_str()))));
+
+        for (table, definition) in edges.filter(|(_, d)| d.contains("->") && !d.contains(FILE_KEY_PREFIX)) {
+            let edge = Edge::from_cascade(table, definition)?;
+            let child_key = String::from(&edge.table) + "." + &edge.column;
+            let parent_key = String::from(&edge.target_table) + "." + &edge.target_column;
+
+            // walk down: children referencing a reached parent
+            tables.entry(edge.table.to_owned())
+                .or_insert_with(SeedTableChecks::new)
+                .add_link(&edge.table, &edge.column, parent_key, false)?;
+
+            // walk up: parents referenced by a reached child
+            tables.entry(edge.target_table.to_owned())
+                .or_insert_with(SeedTableChecks::new)
+                .add_link(&edge.target_table, &edge.target_column, child_key, true)?;
+        }
+
+        let mut tables: Vec<(String, SeedTableChecks)> = tables.into_iter().collect();
+        tables.sort_by_cached_key(|(table, _)| (table_order.iter().position(|t| t == table).unwrap_or(table_order.len()), table.to_owned()));
+        Ok(Self { tables, depth: seed.depth })
+    }
+
+    pub fn should_continue(&self, iteration: usize) -> bool {
+        self.depth.is_none_or(|depth| iteration < depth)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&String, &SeedTableChecks)> {
+        self.tables.iter().map(|(table, checks)| (table, checks))
+    }
+}
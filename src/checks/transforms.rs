@@ -0,0 +1,555 @@
+use cel_interpreter::{Context, ExecutionError, Program, Value as CelValue};
+use cel_interpreter::extractors::Arguments;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde::Deserialize;
+use serde_json::{Number, Value as JsonValue};
+use sqlparser::ast::{CharacterLength, DataType};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::checks::{PlainCelTest, PlainCheckType, PlainColumnCheck, Value, ValuesMap};
+use crate::checks::date_shift::DateShiftSpec;
+use crate::checks::fake::FakeKind;
+use crate::checks::json_path::JsonPath;
+use crate::checks::mask::MaskSpec;
+use crate::checks::pseudonym::{is_wide_integer, pseudonymize};
+use crate::checks::vault::{MIN_TOKEN_LENGTH, random_token};
+use crate::lookup::{LookupStore, exact, is_integer, is_text, normalize, unquote};
+use crate::parameters;
+
+#[derive(Debug, Clone)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Transform {
+    Literal(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    // evaluated against the whole row, e.g. `'user' + string(id) + '@example.test'`
+    Computed { cel: String },
+    // generated from the original value, the same value and secret give the same fake
+    Fake { fake: FakeKind, secret: Option<String> },
+    // the value with its format kept, like the domain of an email or the last digits of a card
+    Mask { mask: MaskSpec },
+    // moved by a number of days that only depends on the entity of the row
+    ShiftDate { shift_date: DateShiftSpec },
+    // keyed HMAC of the value, equal within a namespace across tables
+    Pseudonymize {
+        pseudonymize: String,
+        #[serde(skip)]
+        key: Vec<u8>,
+    },
+    // random token recorded in the vault, equal within a namespace across tables and runs
+    Tokenize { tokenize: String },
+    // written as is, e.g. `NOW()`
+    Sql { sql: String },
+    // applied only to the rows the predicate holds for, e.g. `{ "when": "country == 'DE'", "then": null }`
+    Conditional { when: String, then: Box<Transform> },
+    // transforms of the nodes of a JSON column by path, e.g. `"$.contact.email": { "fake": "email" }`
+    Json(BTreeMap<String, Transform>),
+    Null,
+}
+
+impl Transform {
+    // the literal of a fixed transform in the form the column type needs
+    fn encode(&self, data_type: &DataType) -> Result<String, anyhow::Error> {
+        let literal = match self {
+            Transform::Null => String::from("NULL"),
+            Transform::Sql { sql } => sql.to_owned(),
+            Transform::Bool(b) => encode(CelValue::Bool(*b), data_type)?,
+            Transform::Integer(_) | Transform::Float(_) if is_date(data_type) => {
+                return Err(anyhow::anyhow!("a number cannot be stored in a {data_type} column, use a date string"));
+            },
+            Transform::Integer(i) => encode(CelValue::Int(*i), data_type)?,
+            Transform::Float(f) => encode(CelValue::Float(*f), data_type)?,
+            Transform::Literal(value) if is_integer(data_type) => value.trim().parse::<i64>()
+                .map_err(|_| anyhow::anyhow!("{value} is not an integer"))?
+                .to_string(),
+            Transform::Literal(value) if is_decimal(data_type) => {
+                value.trim().parse::<f64>().map_err(|_| anyhow::anyhow!("{value} is not a number"))?;
+                value.trim().to_owned()
+            },
+            Transform::Literal(value) if is_date(data_type) => {
+                let complete = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").is_ok();
+                if !complete && NaiveDate::parse_from_str(value, "%Y-%m-%d").is_err() {
+                    return Err(anyhow::anyhow!("{value} is not a date"));
+                }
+                quote(value)
+            },
+            Transform::Literal(value) => quote(value),
+            Transform::Computed { .. } | Transform::Fake { .. } | Transform::Mask { .. } | Transform::ShiftDate { .. }
+            | Transform::Pseudonymize { .. } | Transform::Tokenize { .. } | Transform::Json(_) | Transform::Conditional { .. } => {
+                return Err(anyhow::anyhow!("transform depends on the row"));
+            },
+        };
+        Ok(literal)
+    }
+
+    pub fn tokenizes(&self) -> bool {
+        match self {
+            Transform::Tokenize { .. } => true,
+            Transform::Conditional { then, .. } => then.tokenizes(),
+            Transform::Json(paths) => paths.values().any(|transform| transform.tokenizes()),
+            _ => false,
+        }
+    }
+}
+
+enum CompiledTransform {
+    Fixed(Transform),
+    Computed(Program),
+    Fake(FakeKind, String),
+    Mask(MaskSpec),
+    ShiftDate(DateShiftSpec),
+    Pseudonymize(String, Vec<u8>),
+    Tokenize(String),
+    Json(Vec<(JsonPath, CompiledTransform)>),
+    Conditional(PlainCheckType, Box<CompiledTransform>),
+}
+
+// secrets and keys are left out, checks get printed
+impl core::fmt::Debug for CompiledTransform {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CompiledTransform::Fixed(transform) => f.debug_tuple("Fixed").field(transform).finish(),
+            CompiledTransform::Computed(program) => f.debug_tuple("Computed").field(program).finish(),
+            CompiledTransform::Fake(fake, _) => f.debug_tuple("Fake").field(fake).finish_non_exhaustive(),
+            CompiledTransform::Mask(_) => f.debug_tuple("Mask").finish_non_exhaustive(),
+            CompiledTransform::ShiftDate(shift) => f.debug_tuple("ShiftDate").field(&shift.entity_column()).finish_non_exhaustive(),
+            CompiledTransform::Pseudonymize(namespace, _) => f.debug_tuple("Pseudonymize").field(namespace).finish_non_exhaustive(),
+            CompiledTransform::Tokenize(namespace) => f.debug_tuple("Tokenize").field(namespace).finish(),
+            CompiledTransform::Json(paths) => f.debug_tuple("Json").field(paths).finish(),
+            CompiledTransform::Conditional(when, then) => f.debug_tuple("Conditional").field(when).field(then).finish(),
+        }
+    }
+}
+
+impl CompiledTransform {
+    fn new(table: &str, column: &str, transform: &Transform) -> Result<Self, anyhow::Error> {
+        let compiled = match transform {
+            Transform::Computed { cel } => CompiledTransform::Computed(
+                Program::compile(cel).map_err(|e| anyhow::anyhow!("cannot compile transform of {column}: {e}"))?
+            ),
+            Transform::Fake { fake, secret } => CompiledTransform::Fake(*fake, secret.to_owned().unwrap_or_default()),
+            Transform::Mask { mask } => CompiledTransform::Mask(mask.to_owned()),
+            Transform::ShiftDate { shift_date } => CompiledTransform::ShiftDate(shift_date.to_owned()),
+            Transform::Pseudonymize { pseudonymize, key } => CompiledTransform::Pseudonymize(pseudonymize.to_owned(), key.to_owned()),
+            Transform::Tokenize { tokenize } => CompiledTransform::Tokenize(tokenize.to_owned()),
+            Transform::Json(paths) => {
+                let mut compiled = Vec::with_capacity(paths.len());
+                for (path, transform) in paths.iter() {
+                    compiled.push((JsonPath::parse(path)?, CompiledTransform::new(table, column, transform)?));
+                }
+                CompiledTransform::Json(compiled)
+            },
+            // the predicate sees the row just like a filter does
+            Transform::Conditional { when, then } => CompiledTransform::Conditional(
+                Box::new(PlainCelTest::new(when, table).map_err(|e| anyhow::anyhow!("cannot compile condition of {column}: {e}"))?),
+                Box::new(CompiledTransform::new(table, column, then)?),
+            ),
+            fixed => CompiledTransform::Fixed(fixed.to_owned()),
+        };
+        Ok(compiled)
+    }
+
+    // variables the computed values reference, columns or parameters
+    fn collect_variables(&self, variables: &mut Vec<String>) {
+        match self {
+            CompiledTransform::Computed(program) => {
+                variables.extend(program.references().variables().iter().map(|v| v.to_string()));
+            },
+            CompiledTransform::Json(paths) => paths.iter().for_each(|(_, transform)| transform.collect_variables(variables)),
+            CompiledTransform::Conditional(_, then) => then.collect_variables(variables),
+            _ => {},
+        }
+    }
+}
+
+// every fixed transform has to fit the type of the column it replaces, computed ones are
+// checked as they are evaluated
+pub fn check_transforms(
+    transforms: &HashMap<String, HashMap<String, Transform>>,
+    data_types: &HashMap<String, HashMap<String, DataType>>,
+) -> Result<(), anyhow::Error> {
+    for (table, columns) in transforms.iter() {
+        let Some(table_data_types) = data_types.get(table) else {
+            return Err(anyhow::anyhow!("cannot transform unknown table {table}"));
+        };
+        for (column, transform) in columns.iter() {
+            let Some(data_type) = table_data_types.get(column) else {
+                return Err(anyhow::anyhow!("cannot transform unknown column {table}.{column}"));
+            };
+            check_transform(table, column, transform, data_type, table_data_types)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_transform(
+    table: &str,
+    column: &str,
+    transform: &Transform,
+    data_type: &DataType,
+    table_data_types: &HashMap<String, DataType>,
+) -> Result<(), anyhow::Error> {
+    match transform {
+        Transform::Computed { .. } => {},
+        Transform::Fake { fake, .. } if !is_text(data_type) => {
+            return Err(anyhow::anyhow!("invalid transform of {table}.{column}: {fake:?} needs a text column"));
+        },
+        Transform::Fake { secret: None, .. } => println!("Fake values of {table}.{column} are not seeded with a secret"),
+        Transform::Fake { .. } => {},
+        Transform::Mask { mask } if is_integer(data_type) && !mask.produces_digits() => {
+            return Err(anyhow::anyhow!("invalid transform of {table}.{column}: the mask of an integer column has to keep digits"));
+        },
+        Transform::Mask { .. } if !is_text(data_type) && !is_integer(data_type) => {
+            return Err(anyhow::anyhow!("invalid transform of {table}.{column}: masks need a text or integer column"));
+        },
+        Transform::Mask { .. } => {},
+        Transform::ShiftDate { .. } if !is_date(data_type) => {
+            return Err(anyhow::anyhow!("invalid transform of {table}.{column}: dates can only be shifted in date columns"));
+        },
+        Transform::ShiftDate { shift_date } if !table_data_types.contains_key(shift_date.entity_column()) => {
+            return Err(anyhow::anyhow!("invalid transform of {table}.{column}: unknown entity column {}", shift_date.entity_column()));
+        },
+        Transform::ShiftDate { shift_date } if !shift_date.has_secret() => println!("Dates of {table}.{column} are not shifted with a secret"),
+        Transform::ShiftDate { .. } => {},
+        Transform::Pseudonymize { .. } if !is_text(data_type) && !is_wide_integer(data_type) => {
+            return Err(anyhow::anyhow!("invalid transform of {table}.{column}: pseudonyms need a text, INT or BIGINT column"));
+        },
+        Transform::Pseudonymize { .. } if is_wide_integer(data_type) => {
+            println!("Pseudonyms of {table}.{column} are not unique, keep the column out of primary and unique keys");
+        },
+        Transform::Pseudonymize { .. } => {},
+        Transform::Tokenize { .. } if !is_text(data_type) && !is_wide_integer(data_type) => {
+            return Err(anyhow::anyhow!("invalid transform of {table}.{column}: tokens need a text, INT or BIGINT column"));
+        },
+        Transform::Tokenize { .. } if is_text(data_type) && max_length(data_type).is_some_and(|length| length < MIN_TOKEN_LENGTH) => {
+            return Err(anyhow::anyhow!("invalid transform of {table}.{column}: tokens need at least {MIN_TOKEN_LENGTH} characters"));
+        },
+        Transform::Tokenize { .. } => {},
+        Transform::Json(_) if !is_text(data_type) && !matches!(data_type, DataType::JSON) => {
+            return Err(anyhow::anyhow!("invalid transform of {table}.{column}: JSON paths need a JSON or text column"));
+        },
+        Transform::Conditional { then, .. } => check_transform(table, column, then, data_type, table_data_types)?,
+        Transform::Json(paths) => {
+            check_json_transforms(paths).map_err(|e| anyhow::anyhow!("invalid transform of {table}.{column}: {e}"))?;
+        },
+        fixed => {
+            fixed.encode(data_type).map_err(|e| anyhow::anyhow!("invalid transform of {table}.{column}: {e}"))?;
+        },
+    }
+    Ok(())
+}
+
+// nodes of a document take any JSON value, only the path and the kind of transform are checked
+fn check_json_transforms(paths: &BTreeMap<String, Transform>) -> Result<(), anyhow::Error> {
+    for (path, transform) in paths.iter() {
+        JsonPath::parse(path)?;
+        check_json_transform(path, transform)?;
+    }
+    Ok(())
+}
+
+fn check_json_transform(path: &str, transform: &Transform) -> Result<(), anyhow::Error> {
+    match transform {
+        Transform::Sql { .. } => return Err(anyhow::anyhow!("SQL cannot be written into the JSON path {path}")),
+        Transform::Json(_) => return Err(anyhow::anyhow!("JSON paths cannot be nested, extend the path {path} instead")),
+        Transform::Conditional { then, .. } => check_json_transform(path, then)?,
+        Transform::Float(f) if !f.is_finite() => return Err(anyhow::anyhow!("{f} is not a JSON number")),
+        _ => {},
+    }
+    Ok(())
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('\'');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\'' => quoted.push_str("\\'"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\0' => quoted.push_str("\\0"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+fn max_length(data_type: &DataType) -> Option<usize> {
+    match data_type {
+        DataType::Char(Some(CharacterLength::IntegerLength { length, .. }))
+        | DataType::Varchar(Some(CharacterLength::IntegerLength { length, .. })) => Some(*length as usize),
+        _ => None,
+    }
+}
+
+fn is_decimal(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Decimal(_) | DataType::Numeric(_) | DataType::Dec(_)
+        | DataType::Float(_) | DataType::Double(_) | DataType::Real | DataType::DoublePrecision
+    )
+}
+
+fn is_date(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Date | DataType::Datetime(_) | DataType::Timestamp(..))
+}
+
+fn format_timestamp(timestamp: i64, data_type: &DataType) -> Result<String, anyhow::Error> {
+    let datetime = DateTime::from_timestamp(timestamp, 0).ok_or(anyhow::anyhow!("timestamp {timestamp} is out of range"))?;
+    let format = if matches!(data_type, DataType::Date) { "%Y-%m-%d" } else { "%Y-%m-%d %H:%M:%S" };
+    Ok(quote(&datetime.format(format).to_string()))
+}
+
+// renders the result of a computed transform as a literal of the column's type
+fn encode(value: CelValue, data_type: &DataType) -> Result<String, anyhow::Error> {
+    let literal = match value {
+        CelValue::Null => String::from("NULL"),
+        CelValue::Bool(b) if is_text(data_type) => quote(&b.to_string()),
+        CelValue::Bool(b) => String::from(if b { "1" } else { "0" }),
+        CelValue::Int(i) if is_date(data_type) => format_timestamp(i, data_type)?,
+        CelValue::Int(i) if is_text(data_type) => quote(&i.to_string()),
+        CelValue::Int(i) => i.to_string(),
+        CelValue::UInt(u) if is_text(data_type) => quote(&u.to_string()),
+        CelValue::UInt(u) => u.to_string(),
+        CelValue::Float(f) if is_text(data_type) => quote(&f.to_string()),
+        CelValue::Float(f) if is_integer(data_type) && f.fract() == 0.0 => (f as i64).to_string(),
+        CelValue::Float(f) if is_integer(data_type) => return Err(anyhow::anyhow!("{f} is not an integer")),
+        CelValue::Float(f) => f.to_string(),
+        CelValue::String(s) if is_integer(data_type) => s.trim().parse::<i64>()
+            .map_err(|_| anyhow::anyhow!("{s} is not an integer"))?
+            .to_string(),
+        CelValue::String(s) => quote(&s),
+        other => return Err(anyhow::anyhow!("cannot store {other:?} in a {data_type} column")),
+    };
+    Ok(literal)
+}
+
+fn to_json(value: CelValue) -> Result<JsonValue, anyhow::Error> {
+    let json = match value {
+        CelValue::Null => JsonValue::Null,
+        CelValue::Bool(b) => JsonValue::Bool(b),
+        CelValue::Int(i) => JsonValue::from(i),
+        CelValue::UInt(u) => JsonValue::from(u),
+        CelValue::Float(f) => Number::from_f64(f).map(JsonValue::Number).ok_or(anyhow::anyhow!("{f} is not a JSON number"))?,
+        CelValue::String(s) => JsonValue::String(s.to_string()),
+        CelValue::List(items) => JsonValue::Array(items.iter().cloned().map(to_json).collect::<Result<_, _>>()?),
+        other => return Err(anyhow::anyhow!("cannot store {other:?} in JSON")),
+    };
+    Ok(json)
+}
+
+fn substring(Arguments(args): Arguments) -> Result<CelValue, ExecutionError> {
+    let (Some(CelValue::String(s)), Some(CelValue::Int(start))) = (args.first(), args.get(1)) else {
+        return Err(ExecutionError::function_error("substring", "expects a string and a start index"));
+    };
+    let chars = s.chars().skip(*start as usize);
+    let substring: String = match args.get(2) {
+        Some(CelValue::Int(end)) => chars.take((*end - *start).max(0) as usize).collect(),
+        Some(_) => return Err(ExecutionError::function_error("substring", "end index must be an int")),
+        None => chars.collect(),
+    };
+    Ok(CelValue::String(Arc::new(substring)))
+}
+
+#[derive(Debug, Default)]
+pub struct ColumnTransforms {
+    transforms: Vec<(String, CompiledTransform)>,
+    variables: Vec<String>,
+}
+
+impl ColumnTransforms {
+    pub fn new(table: &str, transforms: Option<&HashMap<String, Transform>>) -> Result<Self, anyhow::Error> {
+        let mut compiled = Vec::new();
+        for (column, transform) in transforms.into_iter().flatten() {
+            compiled.push((column.to_owned(), CompiledTransform::new(table, column, transform)?));
+        }
+        compiled.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut variables = Vec::new();
+        compiled.iter().for_each(|(_, transform)| transform.collect_variables(&mut variables));
+        variables.sort();
+        variables.dedup();
+        Ok(ColumnTransforms { transforms: compiled, variables })
+    }
+
+    // only the referenced columns are parsed, the others may hold values CEL has no type for
+    fn build_context<'a>(variables: &[String], value_per_field: &'a ValuesMap) -> Context<'a> {
+        let mut context = Context::default();
+        context.add_function("timestamp", |d: Arc<String>| PlainCelTest::parse_date(&d));
+        context.add_function("upper", |s: Arc<String>| s.to_uppercase());
+        context.add_function("lower", |s: Arc<String>| s.to_lowercase());
+        context.add_function("substring", substring);
+
+        for variable in variables.iter() {
+            if variable == parameters::NAMESPACE {
+                context.add_variable_from_value(variable, PlainCelTest::parameters_value());
+            } else if let Some((str_value, data_type)) = value_per_field.get(variable) {
+                let value = match Value::parse(str_value, data_type) {
+                    // NULL stays null here, transforms may want to keep it
+                    Value::Null => CelValue::Null,
+                    value => PlainCelTest::to_cel_value(value),
+                };
+                context.add_variable_from_value(variable, value);
+            }
+        }
+        context
+    }
+
+    // new values per column, computed from the row as it was before any transform
+    pub fn apply(&self, value_per_field: &ValuesMap, lookup_table: &mut LookupStore) -> Result<Vec<(String, String)>, anyhow::Error> {
+        let mut context: Option<Context> = None;
+        let mut values = Vec::with_capacity(self.transforms.len());
+        for (column, transform) in self.transforms.iter() {
+            if let Some(value) = self.transform_value(column, transform, &mut context, value_per_field, lookup_table)? {
+                values.push((column.to_owned(), value));
+            }
+        }
+        Ok(values)
+    }
+
+    // the new literal of a column, none when a condition keeps the original value
+    fn transform_value<'a>(
+        &self,
+        column: &str,
+        transform: &CompiledTransform,
+        context: &mut Option<Context<'a>>,
+        value_per_field: &'a ValuesMap,
+        lookup_table: &mut LookupStore,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let Some((str_value, data_type)) = value_per_field.get(column) else {
+            return Err(anyhow::anyhow!("cannot transform unknown column {column}"));
+        };
+        let value = match transform {
+            CompiledTransform::Conditional(when, then) => {
+                if !when.test_row(value_per_field, lookup_table)? {
+                    return Ok(None);
+                }
+                return self.transform_value(column, then, context, value_per_field, lookup_table);
+            },
+            CompiledTransform::Fixed(transform) => transform.encode(data_type)?,
+            CompiledTransform::Computed(program) => {
+                let context = context.get_or_insert_with(|| ColumnTransforms::build_context(&self.variables, value_per_field));
+                let result = program.execute(context).map_err(|e| anyhow::anyhow!("cannot compute {column}: {e}"))?;
+                encode(result, data_type).map_err(|e| anyhow::anyhow!("cannot compute {column}: {e}"))?
+            },
+            CompiledTransform::Fake(..) | CompiledTransform::Mask(..) | CompiledTransform::ShiftDate(..)
+            | CompiledTransform::Pseudonymize(..) | CompiledTransform::Tokenize(_) | CompiledTransform::Json(_) if str_value == "NULL" => {
+                str_value.to_owned()
+            },
+            CompiledTransform::Mask(mask) => {
+                let masked = mask.apply(&unquote(str_value)).map_err(|e| anyhow::anyhow!("cannot mask {column}: {e}"))?;
+                encode(CelValue::String(Arc::new(masked)), data_type).map_err(|e| anyhow::anyhow!("cannot mask {column}: {e}"))?
+            },
+            CompiledTransform::ShiftDate(shift) => {
+                let shifted = shift.shift(&unquote(str_value), &ColumnTransforms::entity(shift, value_per_field)?)
+                    .map_err(|e| anyhow::anyhow!("cannot shift {column}: {e}"))?;
+                quote(&shifted)
+            },
+            CompiledTransform::Pseudonymize(namespace, key) => {
+                let pseudonym = pseudonymize(key, namespace, &exact(str_value, data_type), data_type);
+                match max_length(data_type) {
+                    _ if is_wide_integer(data_type) => pseudonym,
+                    Some(length) => quote(&pseudonym.chars().take(length).collect::<String>()),
+                    None => quote(&pseudonym),
+                }
+            },
+            CompiledTransform::Tokenize(namespace) => {
+                let token = lookup_table.tokenize(namespace, str_value, data_type, || random_token(data_type, max_length(data_type)))
+                    .map_err(|e| anyhow::anyhow!("cannot tokenize {column}: {e}"))?;
+                if is_wide_integer(data_type) { token } else { quote(&token) }
+            },
+            CompiledTransform::Json(paths) => {
+                let mut document: JsonValue = serde_json::from_str(&unquote(str_value))
+                    .map_err(|e| anyhow::anyhow!("cannot parse JSON of {column}: {e}"))?;
+                for (path, transform) in paths.iter() {
+                    for node in path.select_mut(&mut document) {
+                        *node = self.transform_node(transform, node, context, value_per_field, lookup_table)
+                            .map_err(|e| anyhow::anyhow!("cannot transform {column}: {e}"))?;
+                    }
+                }
+                quote(&serde_json::to_string(&document)?)
+            },
+            CompiledTransform::Fake(fake, secret) => {
+                let generated = fake.generate(secret, &unquote(str_value));
+                // cut to the column size, strict imports reject longer values
+                match max_length(data_type) {
+                    Some(length) => quote(&generated.chars().take(length).collect::<String>()),
+                    None => quote(&generated),
+                }
+            },
+        };
+        Ok(Some(value))
+    }
+
+    // rows without an entity share one offset
+    fn entity(shift: &DateShiftSpec, value_per_field: &ValuesMap) -> Result<String, anyhow::Error> {
+        let Some((str_value, data_type)) = value_per_field.get(shift.entity_column()) else {
+            return Err(anyhow::anyhow!("unknown entity column {}", shift.entity_column()));
+        };
+        Ok(normalize(str_value, data_type, false).into_owned())
+    }
+
+    // the new value of a node inside a JSON document, numbers stay numbers where the transform allows it
+    fn transform_node<'a>(
+        &self,
+        transform: &CompiledTransform,
+        node: &JsonValue,
+        context: &mut Option<Context<'a>>,
+        value_per_field: &'a ValuesMap,
+        lookup_table: &mut LookupStore,
+    ) -> Result<JsonValue, anyhow::Error> {
+        let text = match node {
+            JsonValue::String(s) => s.to_owned(),
+            JsonValue::Number(n) => n.to_string(),
+            JsonValue::Bool(b) => b.to_string(),
+            _ => String::new(),
+        };
+        let value = match transform {
+            CompiledTransform::Conditional(when, then) => {
+                if !when.test_row(value_per_field, lookup_table)? {
+                    return Ok(node.to_owned());
+                }
+                return self.transform_node(then, node, context, value_per_field, lookup_table);
+            },
+            CompiledTransform::Fixed(Transform::Literal(s)) => JsonValue::String(s.to_owned()),
+            CompiledTransform::Fixed(Transform::Integer(i)) => JsonValue::from(*i),
+            CompiledTransform::Fixed(Transform::Float(f)) => to_json(CelValue::Float(*f))?,
+            CompiledTransform::Fixed(Transform::Bool(b)) => JsonValue::Bool(*b),
+            CompiledTransform::Fixed(Transform::Null) => JsonValue::Null,
+            CompiledTransform::Fixed(other) => return Err(anyhow::anyhow!("{other:?} cannot be written into JSON")),
+            CompiledTransform::Computed(program) => {
+                let context = context.get_or_insert_with(|| ColumnTransforms::build_context(&self.variables, value_per_field));
+                to_json(program.execute(context)?)?
+            },
+            CompiledTransform::Json(_) => return Err(anyhow::anyhow!("JSON paths cannot be nested")),
+            _ if node.is_null() => JsonValue::Null,
+            _ if node.is_object() || node.is_array() => return Err(anyhow::anyhow!("cannot mask or replace a whole JSON {node}")),
+            CompiledTransform::Fake(fake, secret) => JsonValue::String(fake.generate(secret, &text)),
+            CompiledTransform::Mask(mask) => {
+                let masked = mask.apply(&text)?;
+                match masked.parse::<Number>() {
+                    Ok(number) if node.is_number() => JsonValue::Number(number),
+                    _ => JsonValue::String(masked),
+                }
+            },
+            CompiledTransform::ShiftDate(shift) => JsonValue::String(shift.shift(&text, &ColumnTransforms::entity(shift, value_per_field)?)?),
+            CompiledTransform::Pseudonymize(namespace, key) if node.is_i64() || node.is_u64() => {
+                JsonValue::from(pseudonymize(key, namespace, &text, &DataType::BigInt(None)).parse::<u64>()?)
+            },
+            CompiledTransform::Pseudonymize(namespace, key) => JsonValue::String(pseudonymize(key, namespace, &text, &DataType::Text)),
+            CompiledTransform::Tokenize(namespace) if node.is_i64() || node.is_u64() => {
+                let data_type = DataType::BigInt(None);
+                JsonValue::from(lookup_table.tokenize(namespace, &text, &data_type, || random_token(&data_type, None))?.parse::<u64>()?)
+            },
+            CompiledTransform::Tokenize(namespace) => {
+                JsonValue::String(lookup_table.tokenize(namespace, &text, &DataType::Text, || random_token(&DataType::Text, None))?)
+            },
+        };
+        Ok(value)
+    }
+}
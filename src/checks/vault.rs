@@ -0,0 +1,113 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{Key, KeyInit, XChaCha20Poly1305, XNonce};
+use serde::Deserialize;
+use sqlparser::ast::DataType;
+use std::path::{Path, PathBuf};
+
+use crate::checks::pseudonym::{INTEGER_RANGE, KeySource, is_wide_integer};
+use crate::lookup::LookupStore;
+
+// the salt and nonce follow, then the tokens as JSON sealed with XChaCha20-Poly1305
+const MAGIC: &[u8] = b"MDFVAULT1";
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 24;
+// random bytes rendered into text tokens
+const TOKEN_BYTES: usize = 10;
+const TOKEN_PREFIX: &str = "tok_";
+// text tokens are cut to the column size, shorter ones would run out of free tokens
+pub const MIN_TOKEN_LENGTH: usize = 12;
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct VaultConfig {
+    // next to the output by default
+    path: Option<PathBuf>,
+    key: KeySource,
+}
+
+impl VaultConfig {
+    pub fn path(&self, output_file: &Path) -> PathBuf {
+        self.path.to_owned().unwrap_or_else(|| output_file.with_extension("vault"))
+    }
+
+    pub fn configured_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn read_key(&self) -> Result<Vec<u8>, anyhow::Error> {
+        self.key.read()
+    }
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N], anyhow::Error> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).map_err(|e| anyhow::anyhow!("cannot draw random bytes: {e}"))?;
+    Ok(bytes)
+}
+
+// a token that says nothing about the value, integer columns get integers so that they still fit
+pub fn random_token(data_type: &DataType, max_length: Option<usize>) -> Result<String, anyhow::Error> {
+    let bytes: [u8; TOKEN_BYTES] = random_bytes()?;
+    if is_wide_integer(data_type) {
+        let random = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        return Ok(((random % INTEGER_RANGE) + 1).to_string());
+    }
+    let token = String::from(TOKEN_PREFIX) + &bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+    Ok(match max_length {
+        Some(length) => token.chars().take(length).collect(),
+        None => token,
+    })
+}
+
+// the vault key is a passphrase, Argon2id stretches it with the salt of the vault file
+fn cipher(key: &[u8], salt: &[u8]) -> Result<XChaCha20Poly1305, anyhow::Error> {
+    let mut derived = [0u8; 32];
+    Argon2::default().hash_password_into(key, salt, &mut derived)
+        .map_err(|e| anyhow::anyhow!("cannot derive the vault key: {e}"))?;
+    Ok(XChaCha20Poly1305::new(Key::from_slice(&derived)))
+}
+
+// the tokens of a previous run, a vault that does not exist yet is empty
+pub fn load_vault(path: &Path, key: &[u8], lookup_table: &mut LookupStore) -> Result<(), anyhow::Error> {
+    if !path.exists() {
+        println!("Starting a new vault at {}", path.display());
+        return Ok(());
+    }
+    let contents = std::fs::read(path).map_err(|e| anyhow::anyhow!("cannot read vault {}: {e}", path.display()))?;
+    let Some(sealed) = contents.strip_prefix(MAGIC).filter(|sealed| sealed.len() >= SALT_SIZE + NONCE_SIZE) else {
+        return Err(anyhow::anyhow!("{} is not a vault", path.display()));
+    };
+    let (salt, rest) = sealed.split_at(SALT_SIZE);
+    let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+
+    // the header is authenticated along with the tokens
+    let payload = Payload { msg: ciphertext, aad: &[MAGIC, salt].concat() };
+    let plaintext = cipher(key, salt)?.decrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| anyhow::anyhow!("cannot open vault {}, the key is wrong or the file was changed", path.display()))?;
+
+    let tokens: Vec<(String, String, String)> = serde_json::from_slice(&plaintext)?;
+    println!("Loaded {} tokens from vault {}", tokens.len(), path.display());
+    for (namespace, token, original) in tokens.iter() {
+        lookup_table.add_token(namespace, token, original)?;
+    }
+    Ok(())
+}
+
+pub fn save_vault(path: &Path, key: &[u8], lookup_table: &LookupStore) -> Result<(), anyhow::Error> {
+    let tokens = lookup_table.tokens();
+    let plaintext = serde_json::to_vec(&tokens)?;
+    // every save draws a new salt and nonce, a nonce is never used twice with a key
+    let salt: [u8; SALT_SIZE] = random_bytes()?;
+    let nonce: [u8; NONCE_SIZE] = random_bytes()?;
+    let payload = Payload { msg: &plaintext, aad: &[MAGIC, &salt].concat() };
+    let ciphertext = cipher(key, &salt)?.encrypt(XNonce::from_slice(&nonce), payload)
+        .map_err(|_| anyhow::anyhow!("cannot seal vault {}", path.display()))?;
+
+    // replaced at once, an interrupted write leaves the previous vault in place
+    let tmp_path = path.with_extension("vault.tmp");
+    std::fs::write(&tmp_path, [MAGIC, &salt, &nonce, &ciphertext].concat())?;
+    std::fs::rename(&tmp_path, path)?;
+    println!("Saved {} tokens to vault {}", tokens.len(), path.display());
+    Ok(())
+}
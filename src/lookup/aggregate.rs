@@ -0,0 +1,75 @@
+use sqlparser::ast::DataType;
+
+use crate::lookup::{ENTRY_OVERHEAD, LookupStore, normalize, unquote};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Max,
+    Min,
+}
+
+impl AggregateFunction {
+    // `count` or `sum:column`, `max:column`, `min:column`
+    pub fn parse(spec: &str) -> Result<(Self, Option<&str>), anyhow::Error> {
+        let (name, column) = match spec.split_once(':') {
+            Some((name, column)) => (name, Some(column)),
+            None => (spec, None),
+        };
+        let function = match name {
+            "count" => AggregateFunction::Count,
+            "sum" => AggregateFunction::Sum,
+            "max" => AggregateFunction::Max,
+            "min" => AggregateFunction::Min,
+            _ => return Err(anyhow::anyhow!("unknown aggregate function {name}")),
+        };
+        match (function, column) {
+            (AggregateFunction::Count, Some(_)) => Err(anyhow::anyhow!("count takes no column in {spec}")),
+            (AggregateFunction::Count, None) => Ok((function, None)),
+            (_, None) => Err(anyhow::anyhow!("{name} needs a column, as in {name}:column")),
+            (_, Some(column)) => Ok((function, Some(column))),
+        }
+    }
+
+    fn combine(&self, current: Option<f64>, operand: f64) -> f64 {
+        match (self, current) {
+            (_, None) => operand,
+            (AggregateFunction::Count | AggregateFunction::Sum, Some(current)) => current + operand,
+            (AggregateFunction::Max, Some(current)) => current.max(operand),
+            (AggregateFunction::Min, Some(current)) => current.min(operand),
+        }
+    }
+}
+
+// A group is updated by every row of the pre-pass in no particular order, so groups stay in
+// memory; each new one counts against the budget and key sets are spilled to make room.
+impl LookupStore {
+    pub fn accumulate(
+        &mut self,
+        key: &str,
+        function: AggregateFunction,
+        value: &str,
+        data_type: &DataType,
+        operand: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        let operand = match (function, operand) {
+            (AggregateFunction::Count, _) => 1.0,
+            // NULLs are ignored, as in SQL
+            (_, None) | (_, Some("NULL")) => return Ok(()),
+            (_, Some(operand)) => unquote(operand).trim().parse::<f64>()
+                .map_err(|_| anyhow::anyhow!("cannot aggregate {operand} into {key}"))?,
+        };
+
+        let value = normalize(value, data_type, self.fold_case).into_owned();
+        let groups = self.aggregates.entry(key.to_owned()).or_default();
+        let current = groups.get(&value).copied();
+        let bytes = if current.is_none() { value.len() + ENTRY_OVERHEAD } else { 0 };
+        groups.insert(value, function.combine(current, operand));
+        self.reserve(bytes)
+    }
+
+    pub fn aggregate(&self, key: &str, value: &str, data_type: &DataType) -> Option<f64> {
+        self.aggregates.get(key)?.get(normalize(value, data_type, self.fold_case).as_ref()).copied()
+    }
+}
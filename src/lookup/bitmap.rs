@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+// a container switches from a sorted array to a bitmap past this many values,
+// which is where both take 8KB
+const ARRAY_LIMIT: usize = 4096;
+const BITMAP_WORDS: usize = 1024;
+
+#[derive(Debug)]
+enum Container {
+    Array(Vec<u16>),
+    Bitmap(Box<[u64; BITMAP_WORDS]>),
+}
+
+impl Container {
+    fn contains(&self, low: u16) -> bool {
+        match self {
+            Container::Array(values) => values.binary_search(&low).is_ok(),
+            Container::Bitmap(words) => words[low as usize / 64] & (1 << (low % 64)) != 0,
+        }
+    }
+
+    fn insert(&mut self, low: u16) -> bool {
+        match self {
+            Container::Array(values) => {
+                let Err(position) = values.binary_search(&low) else { return false };
+                values.insert(position, low);
+                if values.len() > ARRAY_LIMIT {
+                    let mut words = Box::new([0u64; BITMAP_WORDS]);
+                    for value in values.iter() {
+                        words[*value as usize / 64] |= 1 << (value % 64);
+                    }
+                    *self = Container::Bitmap(words);
+                }
+                true
+            },
+            Container::Bitmap(words) => {
+                let word = &mut words[low as usize / 64];
+                let bit = 1 << (low % 64);
+                let inserted = *word & bit == 0;
+                *word |= bit;
+                inserted
+            },
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item=u16> + '_> {
+        match self {
+            Container::Array(values) => Box::new(values.iter().copied()),
+            Container::Bitmap(words) => Box::new((0..=u16::MAX).filter(|low| words[*low as usize / 64] & (1 << (low % 64)) != 0)),
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        match self {
+            Container::Array(values) => values.capacity() * 2,
+            Container::Bitmap(_) => BITMAP_WORDS * 8,
+        }
+    }
+}
+
+// Roaring-style set of integers: values are bucketed by their high bits and every bucket
+// keeps the low 16 bits either as a sorted array or, once dense, as a bitmap.
+#[derive(Debug, Default)]
+pub struct IntSet {
+    containers: HashMap<i64, Container>,
+    len: usize,
+}
+
+impl IntSet {
+    fn split(value: i64) -> (i64, u16) {
+        (value >> 16, (value & 0xffff) as u16)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        let (high, low) = IntSet::split(value);
+        self.containers.get(&high).is_some_and(|container| container.contains(low))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=i64> + '_ {
+        self.containers.iter().flat_map(|(high, container)| container.iter().map(move |low| (high << 16) | low as i64))
+    }
+
+    // returns the growth in bytes
+    pub fn insert(&mut self, value: i64) -> usize {
+        let (high, low) = IntSet::split(value);
+        let container = self.containers.entry(high).or_insert_with(|| Container::Array(Vec::new()));
+        let before = container.bytes();
+        if container.insert(low) {
+            self.len += 1;
+        }
+        container.bytes().saturating_sub(before)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn container_turns_into_bitmap_past_the_array_limit() {
+        let mut container = Container::Array(Vec::new());
+        // inserted out of order, the array has to stay sorted
+        let values: Vec<u16> = (0..=ARRAY_LIMIT as u16).map(|i| i.wrapping_mul(7919)).collect();
+        for value in values.iter().take(ARRAY_LIMIT) {
+            assert!(container.insert(*value));
+        }
+        assert!(matches!(container, Container::Array(_)));
+        assert!(!container.insert(values[0]));
+
+        assert!(container.insert(values[ARRAY_LIMIT]));
+        assert!(matches!(container, Container::Bitmap(_)));
+        assert!(!container.insert(values[ARRAY_LIMIT]));
+        assert_eq!(container.bytes(), BITMAP_WORDS * 8);
+
+        let mut expected = values.clone();
+        expected.sort();
+        assert_eq!(container.iter().collect::<Vec<u16>>(), expected);
+        assert!(values.iter().all(|value| container.contains(*value)));
+        assert!(!container.contains(1));
+    }
+
+    #[test]
+    fn int_set_keeps_negative_values_apart() {
+        let mut set = IntSet::default();
+        let values = [0, 1, -1, 65535, 65536, -65536, -65537, i64::MIN, i64::MAX];
+        for value in values.iter() {
+            set.insert(*value);
+        }
+        set.insert(-1);
+
+        assert_eq!(set.len(), values.len());
+        assert!(values.iter().all(|value| set.contains(*value)));
+        for absent in [2, -2, 65537, -65535, i64::MIN + 1, i64::MAX - 1] {
+            assert!(!set.contains(absent), "{absent}");
+        }
+        let mut iterated: Vec<i64> = set.iter().collect();
+        iterated.sort();
+        let mut expected = values.to_vec();
+        expected.sort();
+        assert_eq!(iterated, expected);
+    }
+}
@@ -0,0 +1,271 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// every INDEX_STRIDE-th record of a run is kept in memory to binary search on
+const INDEX_STRIDE: usize = 128;
+// runs are merged into one once there are this many of them
+const MAX_RUNS: usize = 16;
+
+// several stores may spill the same key into one directory, every set of runs gets its own id
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn read_record<R: Read>(reader: &mut R) -> Result<Option<String>, anyhow::Error> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {},
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let mut value = vec![0u8; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut value)?;
+    Ok(Some(String::from_utf8(value)?))
+}
+
+fn write_record<W: Write>(writer: &mut W, value: &str) -> Result<u64, anyhow::Error> {
+    writer.write_all(&(value.len() as u32).to_le_bytes())?;
+    writer.write_all(value.as_bytes())?;
+    Ok(4 + value.len() as u64)
+}
+
+struct RunWriter {
+    filepath: PathBuf,
+    writer: BufWriter<File>,
+    index: Vec<(String, u64)>,
+    offset: u64,
+    len: usize,
+}
+
+impl RunWriter {
+    fn create(filepath: &Path) -> Result<Self, anyhow::Error> {
+        Ok(RunWriter {
+            filepath: filepath.to_owned(),
+            writer: BufWriter::new(File::create(filepath)?),
+            index: Vec::new(),
+            offset: 0,
+            len: 0,
+        })
+    }
+
+    fn push(&mut self, value: &str) -> Result<(), anyhow::Error> {
+        if self.len.is_multiple_of(INDEX_STRIDE) {
+            self.index.push((value.to_owned(), self.offset));
+        }
+        self.offset += write_record(&mut self.writer, value)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Run, anyhow::Error> {
+        self.writer.flush()?;
+        Ok(Run { file: File::open(&self.filepath)?, filepath: self.filepath, index: self.index, len: self.len })
+    }
+}
+
+#[derive(Debug)]
+struct Run {
+    filepath: PathBuf,
+    file: File,
+    index: Vec<(String, u64)>,
+    len: usize,
+}
+
+impl Run {
+    fn contains(&self, value: &str) -> Result<bool, anyhow::Error> {
+        let block = self.index.partition_point(|(first, _)| first.as_str() <= value);
+        if block == 0 {
+            return Ok(false);
+        }
+
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(self.index[block - 1].1))?;
+        let mut reader = BufReader::new(file);
+        for _ in 0..INDEX_STRIDE {
+            match read_record(&mut reader)? {
+                Some(record) if record == value => return Ok(true),
+                Some(record) if record.as_str() > value => return Ok(false),
+                Some(_) => {},
+                None => return Ok(false),
+            }
+        }
+        Ok(false)
+    }
+
+    fn reader(&self) -> Result<BufReader<File>, anyhow::Error> {
+        Ok(BufReader::new(File::open(&self.filepath)?))
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.filepath);
+    }
+}
+
+#[derive(Debug)]
+pub struct SortedRuns {
+    dir: PathBuf,
+    id: usize,
+    name: String,
+    runs: Vec<Run>,
+    written: usize,
+}
+
+impl SortedRuns {
+    pub fn new(dir: &Path, name: String) -> Self {
+        SortedRuns {
+            dir: dir.to_owned(),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            name,
+            runs: Vec::new(),
+            written: 0,
+        }
+    }
+
+    fn next_filepath(&mut self) -> PathBuf {
+        self.written += 1;
+        self.dir.join(format!("lookup-{}-{}-{}", self.id, self.name, self.written)).with_extension("keys")
+    }
+
+    pub fn len(&self) -> usize {
+        self.runs.iter().map(|run| run.len).sum()
+    }
+
+    pub fn contains(&self, value: &str) -> Result<bool, anyhow::Error> {
+        for run in self.runs.iter() {
+            if run.contains(value)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn for_each<F: FnMut(&str) -> Result<(), anyhow::Error>>(&self, mut f: F) -> Result<(), anyhow::Error> {
+        for run in self.runs.iter() {
+            let mut reader = run.reader()?;
+            while let Some(value) = read_record(&mut reader)? {
+                f(&value)?;
+            }
+        }
+        Ok(())
+    }
+
+    // values must not be present in any of the existing runs
+    pub fn push(&mut self, mut values: Vec<String>) -> Result<(), anyhow::Error> {
+        values.sort();
+        let mut writer = RunWriter::create(&self.next_filepath())?;
+        for value in values.iter() {
+            writer.push(value)?;
+        }
+        self.runs.push(writer.finish()?);
+
+        if self.runs.len() >= MAX_RUNS {
+            self.merge()?;
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self) -> Result<(), anyhow::Error> {
+        let mut readers = self.runs.iter().map(|run| run.reader()).collect::<Result<Vec<_>, _>>()?;
+        let mut heap = BinaryHeap::new();
+        for (idx, reader) in readers.iter_mut().enumerate() {
+            if let Some(value) = read_record(reader)? {
+                heap.push(Reverse((value, idx)));
+            }
+        }
+
+        let mut writer = RunWriter::create(&self.next_filepath())?;
+        while let Some(Reverse((value, idx))) = heap.pop() {
+            if let Some(next) = read_record(&mut readers[idx])? {
+                heap.push(Reverse((next, idx)));
+            }
+            writer.push(&value)?;
+        }
+
+        self.runs = Vec::from([writer.finish()?]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn keys(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|i| format!("key{i:05}")).collect()
+    }
+
+    #[test]
+    fn run_contains_across_block_boundaries() {
+        let dir = TempDir::new("lookup").unwrap();
+        let mut runs = SortedRuns::new(dir.path(), String::from("test"));
+        // even numbers only, so that every gap between two records can be looked up as well
+        let count = INDEX_STRIDE * 3 + 5;
+        runs.push(keys(0..count * 2).into_iter().step_by(2).collect()).unwrap();
+        assert_eq!(runs.len(), count);
+        assert_eq!(runs.runs[0].index.len(), 4);
+
+        for i in 0..count * 2 {
+            assert_eq!(runs.contains(&format!("key{i:05}")).unwrap(), i % 2 == 0, "key{i:05}");
+        }
+        for boundary in [INDEX_STRIDE - 1, INDEX_STRIDE, 2 * INDEX_STRIDE - 1, 2 * INDEX_STRIDE, count - 1] {
+            assert!(runs.contains(&format!("key{:05}", boundary * 2)).unwrap());
+        }
+        assert!(!runs.contains("a").unwrap());
+        assert!(!runs.contains("key").unwrap());
+        assert!(!runs.contains("zzz").unwrap());
+    }
+
+    #[test]
+    fn merge_keeps_every_value_sorted() {
+        let dir = TempDir::new("lookup").unwrap();
+        let mut runs = SortedRuns::new(dir.path(), String::from("test"));
+        let count = MAX_RUNS * 50;
+        for run in 0..MAX_RUNS {
+            let values: Vec<String> = keys(0..count).into_iter().skip(run).step_by(MAX_RUNS).rev().collect();
+            runs.push(values).unwrap();
+        }
+
+        assert_eq!(runs.runs.len(), 1);
+        assert_eq!(runs.len(), count);
+        let mut merged = Vec::new();
+        runs.for_each(|value| {
+            merged.push(value.to_owned());
+            Ok(())
+        }).unwrap();
+        assert_eq!(merged, keys(0..count));
+        assert!(keys(0..count).iter().all(|key| runs.contains(key).unwrap()));
+        assert!(!runs.contains(&format!("key{count:05}")).unwrap());
+    }
+
+    #[test]
+    fn merged_runs_are_removed() {
+        let dir = TempDir::new("lookup").unwrap();
+        let mut runs = SortedRuns::new(dir.path(), String::from("test"));
+        for run in 0..MAX_RUNS {
+            runs.push(Vec::from([format!("key{run:05}")])).unwrap();
+        }
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn runs_of_the_same_key_do_not_collide() {
+        let dir = TempDir::new("lookup").unwrap();
+        let mut first = SortedRuns::new(dir.path(), String::from("test"));
+        let mut second = SortedRuns::new(dir.path(), String::from("test"));
+        for run in 0..MAX_RUNS {
+            first.push(Vec::from([format!("first{run:05}")])).unwrap();
+            second.push(Vec::from([format!("second{run:05}")])).unwrap();
+        }
+        drop(second);
+
+        assert_eq!(first.len(), MAX_RUNS);
+        assert!((0..MAX_RUNS).all(|run| first.contains(&format!("first{run:05}")).unwrap()));
+        assert!(!first.contains("second00000").unwrap());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
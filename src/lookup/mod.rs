@@ -0,0 +1,318 @@
+mod aggregate;
+mod bitmap;
+mod disk;
+mod persist;
+mod renumber;
+mod tokens;
+
+use sqlparser::ast::DataType;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::lookup::bitmap::IntSet;
+use crate::lookup::disk::SortedRuns;
+use crate::lookup::renumber::IdMap;
+use crate::lookup::tokens::TokenMap;
+
+pub use crate::lookup::aggregate::AggregateFunction;
+
+// rough heap cost of a tracked value on top of its bytes
+const ENTRY_OVERHEAD: usize = 48;
+
+pub fn is_integer(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::TinyInt(_) | DataType::TinyIntUnsigned(_)
+        | DataType::SmallInt(_) | DataType::SmallIntUnsigned(_)
+        | DataType::MediumInt(_) | DataType::MediumIntUnsigned(_)
+        | DataType::Int(_) | DataType::IntUnsigned(_)
+        | DataType::Integer(_) | DataType::IntegerUnsigned(_)
+        | DataType::BigInt(_) | DataType::BigIntUnsigned(_)
+    )
+}
+
+pub fn is_text(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Char(_) | DataType::Varchar(_) | DataType::Text
+        | DataType::TinyText | DataType::MediumText | DataType::LongText
+        | DataType::Enum(..) | DataType::Set(_)
+    )
+}
+
+pub fn unquote(value: &str) -> Cow<'_, str> {
+    let Some(inner) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) else {
+        return Cow::Borrowed(value);
+    };
+    if !inner.contains('\\') && !inner.contains("''") {
+        return Cow::Borrowed(inner);
+    }
+
+    let mut unescaped = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('0') => unescaped.push('\0'),
+                Some('b') => unescaped.push('\x08'),
+                Some('n') => unescaped.push('\n'),
+                Some('r') => unescaped.push('\r'),
+                Some('t') => unescaped.push('\t'),
+                Some('Z') => unescaped.push('\x1a'),
+                Some(other) => unescaped.push(other),
+                None => {},
+            },
+            '\'' => {
+                // doubled quote
+                chars.next();
+                unescaped.push('\'');
+            },
+            _ => unescaped.push(c),
+        }
+    }
+    Cow::Owned(unescaped)
+}
+
+// Brings the literal of a value to the form it is tracked and looked up with, so that
+// `5`, `'5'` and `'005'` all refer to the same key.
+pub fn normalize<'a>(value: &'a str, data_type: &DataType, fold_case: bool) -> Cow<'a, str> {
+    let unquoted = unquote(value);
+
+    if let Ok(parsed) = unquoted.trim().parse::<i64>() {
+        let canonical = parsed.to_string();
+        if canonical != unquoted {
+            return Cow::Owned(canonical);
+        }
+        return unquoted;
+    }
+
+    if fold_case && is_text(data_type) {
+        return Cow::Owned(unquoted.to_lowercase());
+    }
+    unquoted
+}
+
+// The value as a transform keys it: integer columns in canonical form, anything else exactly
+// as stored, so that `'007'` and `'7'` of a text column stay apart.
+pub fn exact<'a>(value: &'a str, data_type: &DataType) -> Cow<'a, str> {
+    if is_integer(data_type) {
+        return normalize(value, data_type, false);
+    }
+    unquote(value)
+}
+
+#[derive(Debug, Default)]
+struct KeySet {
+    // values of integer columns, anything else falls back to the string set
+    integers: IntSet,
+    values: HashSet<String>,
+    bytes: usize,
+    // set once the values outgrow the memory budget
+    spilled: Option<SortedRuns>,
+}
+
+impl KeySet {
+    fn len(&self) -> usize {
+        self.integers.len() + self.values.len() + self.spilled.as_ref().map_or(0, |runs| runs.len())
+    }
+
+    fn contains(&self, value: &str) -> Result<bool, anyhow::Error> {
+        if let Ok(parsed) = value.parse::<i64>() && self.integers.contains(parsed) {
+            return Ok(true);
+        }
+        if self.values.contains(value) {
+            return Ok(true);
+        }
+        match &self.spilled {
+            Some(runs) => runs.contains(value),
+            None => Ok(false),
+        }
+    }
+
+    fn for_each<F: FnMut(&str) -> Result<(), anyhow::Error>>(&self, mut f: F) -> Result<(), anyhow::Error> {
+        for value in self.integers.iter() {
+            f(&value.to_string())?;
+        }
+        for value in self.values.iter() {
+            f(value)?;
+        }
+        match &self.spilled {
+            Some(runs) => runs.for_each(f),
+            None => Ok(()),
+        }
+    }
+
+    fn insert(&mut self, value: &str, as_integer: bool) -> Result<usize, anyhow::Error> {
+        if as_integer && let Ok(parsed) = value.parse::<i64>() {
+            return Ok(self.integers.insert(parsed));
+        }
+
+        if self.contains(value)? {
+            return Ok(0);
+        }
+        self.values.insert(value.to_owned());
+        let bytes = value.len() + ENTRY_OVERHEAD;
+        self.bytes += bytes;
+        Ok(bytes)
+    }
+
+    fn spill(&mut self, dir: &Path, name: String) -> Result<usize, anyhow::Error> {
+        let values: Vec<String> = self.values.drain().collect();
+        self.values.shrink_to_fit();
+        self.spilled.get_or_insert_with(|| SortedRuns::new(dir, name)).push(values)?;
+        Ok(std::mem::take(&mut self.bytes))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LookupStore {
+    sets: HashMap<String, KeySet>,
+    // per group value results of the aggregates, keyed like `orders.customer_id:count`
+    aggregates: HashMap<String, HashMap<String, f64>>,
+    // old to new ids of renumbered primary keys, keyed like `customers.id`
+    id_maps: HashMap<String, IdMap>,
+    // tokens per namespace, loaded from and written to the vault
+    token_maps: HashMap<String, TokenMap>,
+    memory_budget: Option<usize>,
+    memory_used: usize,
+    spill_dir: Option<PathBuf>,
+    // set once the budget is exceeded by values that cannot be spilled
+    over_budget: bool,
+    // for keys compared under case insensitive collations
+    fold_case: bool,
+}
+
+impl LookupStore {
+    pub fn new(memory_budget: Option<usize>, spill_dir: &Path, fold_case: bool) -> Self {
+        LookupStore {
+            sets: HashMap::new(),
+            aggregates: HashMap::new(),
+            id_maps: HashMap::new(),
+            token_maps: HashMap::new(),
+            memory_budget,
+            memory_used: 0,
+            spill_dir: Some(spill_dir.to_owned()),
+            over_budget: false,
+            fold_case,
+        }
+    }
+
+    // a key is tracked once its table was processed, even when no row was kept
+    pub fn start_tracking(&mut self, key: &str) {
+        if !self.sets.contains_key(key) {
+            self.sets.insert(key.to_owned(), KeySet::default());
+        }
+    }
+
+    pub fn has(&self, key: &str) -> bool {
+        self.sets.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sets.values().map(|set| set.len()).sum()
+    }
+
+    pub fn contains(&self, key: &str, value: &str, data_type: &DataType) -> Result<bool, anyhow::Error> {
+        match self.sets.get(key) {
+            Some(set) => set.contains(&normalize(value, data_type, self.fold_case)),
+            None => Ok(false),
+        }
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str, data_type: &DataType) -> Result<(), anyhow::Error> {
+        let value = normalize(value, data_type, self.fold_case);
+        self.insert_normalized(key, &value, is_integer(data_type))
+    }
+
+    fn insert_normalized(&mut self, key: &str, value: &str, as_integer: bool) -> Result<(), anyhow::Error> {
+        let set = match self.sets.get_mut(key) {
+            Some(set) => set,
+            None => self.sets.entry(key.to_owned()).or_default(),
+        };
+        self.memory_used += set.insert(value, as_integer)?;
+        self.enforce_budget()
+    }
+
+    // memory taken by values that stay in memory, key sets are spilled to make room for them
+    fn reserve(&mut self, bytes: usize) -> Result<(), anyhow::Error> {
+        self.memory_used += bytes;
+        self.enforce_budget()
+    }
+
+    fn enforce_budget(&mut self) -> Result<(), anyhow::Error> {
+        if let Some(budget) = self.memory_budget && self.memory_used > budget {
+            self.spill(budget / 2)?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self, target: usize) -> Result<(), anyhow::Error> {
+        let Some(dir) = &self.spill_dir else { return Ok(()) };
+
+        while self.memory_used > target {
+            let Some((key, set)) = self.sets.iter_mut().max_by_key(|(_, set)| set.bytes) else { break };
+            if set.bytes == 0 {
+                if !self.over_budget {
+                    println!("Memory budget exceeded by values that cannot be spilled to disk");
+                    self.over_budget = true;
+                }
+                break;
+            }
+            println!("Spilling {} tracked values of {key} to disk", set.values.len());
+            let name: String = key.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '-' }).collect();
+            self.memory_used -= set.spill(dir, name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unquote_strips_quotes_and_mysql_escapes() {
+        assert_eq!(unquote("42"), "42");
+        assert_eq!(unquote("NULL"), "NULL");
+        assert_eq!(unquote("'plain'"), "plain");
+        assert_eq!(unquote("''"), "");
+        assert_eq!(unquote("'it''s'"), "it's");
+        assert_eq!(unquote(r"'it\'s'"), "it's");
+        assert_eq!(unquote(r"'a\\b'"), r"a\b");
+        assert_eq!(unquote(r"'\0\b\n\r\t\Z'"), "\0\x08\n\r\t\x1a");
+        assert_eq!(unquote(r#"'say \"hi\"'"#), "say \"hi\"");
+        // a lone quote is not a quoted literal
+        assert_eq!(unquote("'"), "'");
+    }
+
+    #[test]
+    fn normalize_canonicalizes_integers() {
+        let int = DataType::Int(None);
+        let text = DataType::Varchar(None);
+        assert_eq!(normalize("5", &int, false), "5");
+        assert_eq!(normalize("'5'", &int, false), "5");
+        assert_eq!(normalize("'005'", &text, false), "5");
+        assert_eq!(normalize("'-007'", &text, false), "-7");
+        assert_eq!(normalize("' 12 '", &text, false), "12");
+        assert_eq!(normalize("'12a'", &text, false), "12a");
+        // out of the i64 range, kept as it is
+        assert_eq!(normalize("'099999999999999999999'", &text, false), "099999999999999999999");
+    }
+
+    #[test]
+    fn exact_canonicalizes_integer_columns_only() {
+        assert_eq!(exact("'007'", &DataType::Varchar(None)), "007");
+        assert_eq!(exact("'it''s'", &DataType::Text), "it's");
+        assert_eq!(exact("007", &DataType::Int(None)), "7");
+        assert_eq!(exact("'-0'", &DataType::BigInt(None)), "0");
+    }
+
+    #[test]
+    fn normalize_folds_the_case_of_text_only() {
+        assert_eq!(normalize("'MiXeD'", &DataType::Varchar(None), true), "mixed");
+        assert_eq!(normalize("'MiXeD'", &DataType::Varchar(None), false), "MiXeD");
+        assert_eq!(normalize("'MiXeD'", &DataType::Blob(None), true), "MiXeD");
+        assert_eq!(normalize(r"'O\'Neil'", &DataType::Text, true), "o'neil");
+    }
+}
@@ -0,0 +1,172 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::lookup::{LookupStore, normalize};
+
+// one value per line, so line breaks and the escape character itself are escaped
+fn encode(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn decode(line: &str) -> String {
+    let mut decoded = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => decoded.push('\n'),
+            Some(other) => decoded.push(other),
+            None => {},
+        }
+    }
+    decoded
+}
+
+// splits a CSV line into its fields, a quoted field may hold commas and doubled quotes
+fn split_fields(line: &str) -> Result<Vec<String>, anyhow::Error> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    // records are read line by line
+                    None => return Err(anyhow::anyhow!("unterminated quoted field in line {line}")),
+                }
+            }
+            while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+            if chars.peek().is_some_and(|c| *c != ',') {
+                return Err(anyhow::anyhow!("unexpected text after a quoted field in line {line}"));
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                field.push(c);
+            }
+            field.truncate(field.trim_end().len());
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+impl LookupStore {
+    pub fn export(&self, dir: &Path) -> Result<(), anyhow::Error> {
+        fs::create_dir_all(dir)?;
+        for (key, set) in self.sets.iter() {
+            println!("Exporting {} tracked values of {key}", set.len());
+            let mut writer = BufWriter::new(File::create(dir.join(format!("{key}.keys")))?);
+            set.for_each(|value| {
+                writer.write_all(encode(value).as_bytes())?;
+                writer.write_all(b"\n")?;
+                Ok(())
+            })?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    // loads one key per line, or a single column of a CSV file with a header row
+    pub fn load_keys(&mut self, key: &str, filepath: &Path, column: Option<&str>) -> Result<(), anyhow::Error> {
+        println!("Loading {key} from {}", filepath.display());
+        let mut lines = BufReader::new(File::open(filepath)?).lines();
+
+        let position = match column {
+            Some(column) => {
+                let header = lines.next().ok_or(anyhow::anyhow!("{} has no header", filepath.display()))??;
+                let position = split_fields(&header)?.iter().position(|name| name == column);
+                Some(position.ok_or(anyhow::anyhow!("{} has no column {column}", filepath.display()))?)
+            },
+            None => None,
+        };
+
+        self.sets.entry(key.to_owned()).or_default();
+        for line in lines {
+            let line = line?;
+            // without a column the whole line is the key, commas included
+            let field = match position {
+                Some(position) => {
+                    let Some(field) = split_fields(&line)?.into_iter().nth(position) else {
+                        return Err(anyhow::anyhow!("{} has no column {position} in line {line}", filepath.display()));
+                    };
+                    field
+                },
+                None => line.trim().to_owned(),
+            };
+            if field.is_empty() {
+                continue;
+            }
+            let value = normalize(&field, &sqlparser::ast::DataType::Text, self.fold_case);
+            self.insert_normalized(key, &value, true)?;
+        }
+        Ok(())
+    }
+
+    pub fn import(&mut self, dir: &Path) -> Result<(), anyhow::Error> {
+        for entry in fs::read_dir(dir)? {
+            let filepath = entry?.path();
+            if filepath.extension().is_none_or(|extension| extension != "keys") {
+                continue;
+            }
+            let Some(key) = filepath.file_stem().and_then(|stem| stem.to_str()) else {
+                return Err(anyhow::anyhow!("cannot determine key of {}", filepath.display()));
+            };
+
+            println!("Importing tracked values of {key}");
+            self.sets.entry(key.to_owned()).or_default();
+            for line in BufReader::new(File::open(&filepath)?).lines() {
+                // exported values are already normalised
+                self.insert_normalized(key, &decode(&line?), true)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_fields_handles_quotes() {
+        assert_eq!(split_fields("1,2,3").unwrap(), ["1", "2", "3"]);
+        assert_eq!(split_fields(" id , name ").unwrap(), ["id", "name"]);
+        assert_eq!(split_fields("a,,").unwrap(), ["a", "", ""]);
+        assert_eq!(split_fields(r#""Doe, Jane",42"#).unwrap(), ["Doe, Jane", "42"]);
+        assert_eq!(split_fields(r#"1, "say ""hi""" ,2"#).unwrap(), ["1", "say \"hi\"", "2"]);
+        assert_eq!(split_fields(r#""""#).unwrap(), [""]);
+        assert!(split_fields(r#"1,"open"#).is_err());
+        assert!(split_fields(r#""a"b,1"#).is_err());
+    }
+
+    #[test]
+    fn load_keys_reads_whole_lines_or_one_column() {
+        let dir = tempdir::TempDir::new("keys").unwrap();
+        let lines = dir.path().join("lines.txt");
+        fs::write(&lines, "a,b\n 007 \n\n").unwrap();
+        let csv = dir.path().join("keys.csv");
+        fs::write(&csv, "name,\"id\"\n\"Doe, Jane\",\"a,b\"\nSmith,7\n").unwrap();
+
+        let mut store = LookupStore::default();
+        store.load_keys("lines", &lines, None).unwrap();
+        store.load_keys("csv", &csv, Some("id")).unwrap();
+        let text = sqlparser::ast::DataType::Text;
+        assert!(store.contains("lines", "'a,b'", &text).unwrap());
+        assert!(store.contains("lines", "7", &text).unwrap());
+        assert!(!store.contains("lines", "'a'", &text).unwrap());
+        assert!(store.contains("csv", "'a,b'", &text).unwrap());
+        assert!(store.contains("csv", "7", &text).unwrap());
+        assert!(!store.contains("csv", "'Smith'", &text).unwrap());
+        assert!(store.load_keys("csv", &csv, Some("missing")).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use sqlparser::ast::DataType;
+use std::collections::HashMap;
+
+use crate::lookup::{ENTRY_OVERHEAD, LookupStore, normalize};
+
+// new ids of a renumbered key, handed out in the order the rows are met
+#[derive(Debug, Default)]
+pub struct IdMap {
+    ids: HashMap<String, i64>,
+    next: i64,
+}
+
+// Every reference to a renumbered key looks its new id up, in the order of the referencing
+// rows, so id maps stay in memory; their entries count against the budget like tracked values.
+impl LookupStore {
+    // the new id of a value, a value met again keeps the id it got first
+    pub fn renumber(&mut self, key: &str, value: &str, data_type: &DataType, start: i64) -> Result<i64, anyhow::Error> {
+        let value = normalize(value, data_type, self.fold_case).into_owned();
+        let map = self.id_maps.entry(key.to_owned()).or_insert_with(|| IdMap { ids: HashMap::new(), next: start });
+        if let Some(id) = map.ids.get(&value) {
+            return Ok(*id);
+        }
+        let id = map.next;
+        let bytes = value.len() + ENTRY_OVERHEAD;
+        map.ids.insert(value, id);
+        map.next += 1;
+        self.reserve(bytes)?;
+        Ok(id)
+    }
+
+    pub fn renumbered(&self, key: &str, value: &str, data_type: &DataType) -> Option<i64> {
+        self.id_maps.get(key)?.ids.get(normalize(value, data_type, self.fold_case).as_ref()).copied()
+    }
+
+    // the id the next row would get, none when the key has not been renumbered
+    pub fn next_id(&self, key: &str) -> Option<i64> {
+        self.id_maps.get(key).map(|map| map.next)
+    }
+}
@@ -0,0 +1,81 @@
+use sqlparser::ast::DataType;
+use std::collections::HashMap;
+
+use crate::lookup::{ENTRY_OVERHEAD, LookupStore, exact};
+
+// attempts at drawing a token that is not taken yet before giving up
+const MAX_ATTEMPTS: usize = 16;
+
+// tokens of a namespace both ways, originals are kept exactly, integers in canonical form
+#[derive(Default)]
+pub struct TokenMap {
+    originals: HashMap<String, String>,
+    tokens: HashMap<String, String>,
+}
+
+// the originals are what the vault protects, they are never printed
+impl core::fmt::Debug for TokenMap {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TokenMap").field("tokens", &self.tokens.len()).finish_non_exhaustive()
+    }
+}
+
+impl TokenMap {
+    fn entry_bytes(token: &str, original: &str) -> usize {
+        2 * (token.len() + original.len() + ENTRY_OVERHEAD)
+    }
+}
+
+// The vault is read into and written from token maps as a whole, so they stay in memory;
+// a token counts against the budget once for each direction it is mapped in.
+impl LookupStore {
+    // the token of a value, values without one get the first drawn token that is still free
+    pub fn tokenize<F: FnMut() -> Result<String, anyhow::Error>>(
+        &mut self,
+        namespace: &str,
+        value: &str,
+        data_type: &DataType,
+        mut draw: F,
+    ) -> Result<String, anyhow::Error> {
+        let original = exact(value, data_type).into_owned();
+        let map = self.token_maps.entry(namespace.to_owned()).or_default();
+        if let Some(token) = map.originals.get(&original) {
+            return Ok(token.to_owned());
+        }
+        for _ in 0..MAX_ATTEMPTS {
+            let token = draw()?;
+            if !map.tokens.contains_key(&token) {
+                let bytes = TokenMap::entry_bytes(&token, &original);
+                map.tokens.insert(token.to_owned(), original.to_owned());
+                map.originals.insert(original, token.to_owned());
+                self.reserve(bytes)?;
+                return Ok(token);
+            }
+        }
+        Err(anyhow::anyhow!("no free token left in namespace {namespace}"))
+    }
+
+    pub fn add_token(&mut self, namespace: &str, token: &str, original: &str) -> Result<(), anyhow::Error> {
+        let map = self.token_maps.entry(namespace.to_owned()).or_default();
+        map.tokens.insert(token.to_owned(), original.to_owned());
+        map.originals.insert(original.to_owned(), token.to_owned());
+        self.reserve(TokenMap::entry_bytes(token, original))
+    }
+
+    // the original of a token, looked up in every namespace unless one is given
+    pub fn detokenize(&self, namespace: Option<&str>, token: &str) -> Option<&str> {
+        self.token_maps.iter()
+            .filter(|(name, _)| namespace.is_none_or(|namespace| namespace == *name))
+            .find_map(|(_, map)| map.tokens.get(token))
+            .map(|original| original.as_str())
+    }
+
+    // namespace, token and original of every token, sorted
+    pub fn tokens(&self) -> Vec<(&str, &str, &str)> {
+        let mut tokens: Vec<(&str, &str, &str)> = self.token_maps.iter()
+            .flat_map(|(namespace, map)| map.tokens.iter().map(move |(token, original)| (namespace.as_str(), token.as_str(), original.as_str())))
+            .collect();
+        tokens.sort();
+        tokens
+    }
+}
@@ -1,23 +1,44 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use tempdir::TempDir;
 
 mod checks;
+mod lookup;
+mod parameters;
 mod scanner;
 
-use checks::get_passes;
-use scanner::{explode_to_files, gather, process_table_inserts};
+use checks::{Condition, FILE_KEY_PREFIX, JoinTable, KeySource, RenumberConfig, Renumbering, SeedConfig, SeedWalk, Transform, VaultConfig, check_transforms, condition_columns, expand_table_patterns, get_passes, get_table_order, is_table_pattern, load_vault, resolve_rule_sets, save_vault};
+use lookup::LookupStore;
+use parameters::Parameter;
+use scanner::{explode_to_files, gather, get_table_columns, get_table_data_types, get_table_foreign_keys, process_table_inserts, scan_table_inserts, set_auto_increments};
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct KeyFile {
+    path: PathBuf,
+    column: Option<String>,
+}
 
 #[derive(Debug)]
 #[derive(Deserialize)]
 #[serde(rename = "name")]
 pub struct Config {
     allow_data_on_tables: Option<HashSet<String>>,
-    cascades: HashMap<String, Vec<String>>,
-    text_transforms: HashMap<String, HashMap<String, String>>,
-    filters: HashMap<String, Vec<String>>
+    cascades: HashMap<String, Vec<Condition>>,
+    text_transforms: HashMap<String, HashMap<String, Transform>>,
+    filters: HashMap<String, Vec<Condition>>,
+    join_tables: Option<HashMap<String, JoinTable>>,
+    seed: Option<SeedConfig>,
+    strict: Option<bool>,
+    case_insensitive_keys: Option<bool>,
+    key_files: Option<HashMap<String, KeyFile>>,
+    rule_sets: Option<HashMap<String, Vec<Condition>>>,
+    parameters: Option<HashMap<String, Parameter>>,
+    pseudonymization_key: Option<KeySource>,
+    renumber: Option<HashMap<String, RenumberConfig>>,
+    vault: Option<VaultConfig>,
 }
 
 impl Config {
@@ -30,48 +51,284 @@ impl Config {
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
     #[clap(value_name = "FILE", required=true)]
-    input: PathBuf,
+    input: Option<PathBuf>,
     #[clap(short, long, required = true)]
-    config: PathBuf,
+    config: Option<PathBuf>,
     #[clap(short, long, required = true)]
-    output: PathBuf,
+    output: Option<PathBuf>,
     #[clap(short, long, required = false)]
     working_dir: Option<PathBuf>,
+    /// Megabytes of tracked keys to hold in memory before spilling them to the working dir
+    #[clap(long, required = false)]
+    memory_budget: Option<usize>,
+    /// Directory to write the tracked key sets to once all passes are done
+    #[clap(long, value_name = "DIR", required = false)]
+    export_keys: Option<PathBuf>,
+    /// Directory of previously exported key sets to start the lookups with
+    #[clap(long, value_name = "DIR", required = false)]
+    import_keys: Option<PathBuf>,
+    /// Value of a config parameter, as in --set tenant_id=42
+    #[clap(long = "set", value_name = "NAME=VALUE", required = false)]
+    set: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Looks up the original values of tokens in the vault
+    Detokenize {
+        #[clap(short, long, required = true)]
+        config: PathBuf,
+        /// Vault file, the one of the config by default
+        #[clap(long, value_name = "FILE", required = false)]
+        vault: Option<PathBuf>,
+        /// Namespace to look the tokens up in, every one by default
+        #[clap(long, required = false)]
+        namespace: Option<String>,
+        #[clap(value_name = "TOKEN", required = true)]
+        tokens: Vec<String>,
+    },
+}
+
+fn walk_seed<F: Fn() -> LookupStore>(
+    seed: &SeedConfig,
+    cascades: &HashMap<String, Vec<Condition>>,
+    join_tables: &HashMap<String, JoinTable>,
+    working_file_path: &Path,
+    new_store: F,
+) -> Result<LookupStore, anyhow::Error> {
+    let walk = SeedWalk::new(seed, cascades, join_tables, &get_table_order(cascades.iter(), join_tables)?)?;
+    let mut lookup_table = new_store();
+    let mut iteration = 0;
+    let mut converged = false;
+    while walk.should_continue(iteration) {
+        // every walk re-tracks all reached rows, so the previous store can be handed over as a whole
+        let previous = std::mem::replace(&mut lookup_table, new_store());
+        for (table, table_checks) in walk.iter() {
+            process_table_inserts(
+                working_file_path,
+                table,
+                |statement| {
+                    table_checks.track(statement, &previous, None, &mut lookup_table)
+                },
+            )?;
+        }
+        iteration += 1;
+        if lookup_table.len() == previous.len() {
+            converged = true;
+            break;
+        }
+    }
+
+    // The depth cuts the walk one hop past the tracked rows. Those rows are walked up from
+    // until no parent is missing, so that the kept rows are exactly the tracked ones and none
+    // of them references a row left out.
+    let last_hop = if converged { None } else { Some(std::mem::replace(&mut lookup_table, new_store())) };
+    if let Some(last_hop) = &last_hop {
+        loop {
+            let previous = std::mem::replace(&mut lookup_table, new_store());
+            for (table, table_checks) in walk.iter() {
+                process_table_inserts(
+                    working_file_path,
+                    table,
+                    |statement| {
+                        table_checks.track(statement, last_hop, Some(&previous), &mut lookup_table)
+                    },
+                )?;
+            }
+            if lookup_table.len() == previous.len() {
+                break;
+            }
+        }
+    }
+
+    for (table, table_checks) in walk.iter() {
+        process_table_inserts(
+            working_file_path,
+            table,
+            |statement| {
+                match &last_hop {
+                    Some(last_hop) => table_checks.apply(statement, last_hop, Some(&lookup_table)),
+                    None => table_checks.apply(statement, &lookup_table, None),
+                }
+            },
+        )?;
+    }
+    Ok(lookup_table)
+}
+
+// fills in parameters and the pseudonymization key, which is read once on first use
+fn prepare_transform(
+    transform: &mut Transform,
+    key_source: Option<&KeySource>,
+    pseudonymization_key: &mut Option<Vec<u8>>,
+) -> Result<(), anyhow::Error> {
+    match transform {
+        Transform::Literal(value) => *value = parameters::substitute(value)?,
+        Transform::Fake { secret: Some(secret), .. } => *secret = parameters::substitute(secret)?,
+        Transform::Mask { mask } => {
+            if let Some(secret) = mask.secret_mut() {
+                *secret = parameters::substitute(secret)?;
+            }
+        },
+        Transform::ShiftDate { shift_date } => {
+            if let Some(secret) = shift_date.secret_mut() {
+                *secret = parameters::substitute(secret)?;
+            }
+        },
+        Transform::Pseudonymize { key, .. } => {
+            if pseudonymization_key.is_none() {
+                let source = key_source.ok_or(anyhow::anyhow!("pseudonymization needs a pseudonymization_key"))?;
+                *pseudonymization_key = Some(source.read()?);
+            }
+            key.clone_from(pseudonymization_key.as_ref().unwrap());
+        },
+        Transform::Conditional { then, .. } => prepare_transform(then, key_source, pseudonymization_key)?,
+        Transform::Json(paths) => {
+            for transform in paths.values_mut() {
+                prepare_transform(transform, key_source, pseudonymization_key)?;
+            }
+        },
+        _ => {},
+    }
+    Ok(())
+}
+
+// prints the original of every token, one `token<TAB>original` line each
+fn detokenize(config_file: &Path, vault: Option<&Path>, namespace: Option<&str>, tokens: &[String]) -> Result<(), anyhow::Error> {
+    let config = Config::from_file(config_file);
+    let vault_config = config.vault.as_ref().ok_or(anyhow::anyhow!("detokenizing needs a vault in the config"))?;
+    let Some(path) = vault.or(vault_config.configured_path()) else {
+        return Err(anyhow::anyhow!("the config has no vault path, pass one with --vault"));
+    };
+    if !path.exists() {
+        return Err(anyhow::anyhow!("vault {} does not exist", path.display()));
+    }
+    let mut lookup_table = LookupStore::default();
+    load_vault(path, &vault_config.read_key()?, &mut lookup_table)?;
+
+    let mut unknown = Vec::new();
+    for token in tokens.iter() {
+        match lookup_table.detokenize(namespace, token) {
+            Some(original) => println!("{token}\t{original}"),
+            None => unknown.push(token.as_str()),
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(anyhow::anyhow!("unknown tokens {}", unknown.join(", ")));
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), anyhow::Error> {
     let cli = Cli::parse();
-    let input_file = std::env::current_dir().unwrap().to_path_buf().join(cli.input);
-    let output_file = std::env::current_dir().unwrap().to_path_buf().join(cli.output);
-    let config_file = std::env::current_dir().unwrap().to_path_buf().join(cli.config);
+    if let Some(Command::Detokenize { config, vault, namespace, tokens }) = &cli.command {
+        return detokenize(config, vault.as_deref(), namespace.as_deref(), tokens);
+    }
+    // present unless a subcommand was given
+    let (Some(input), Some(output), Some(config)) = (cli.input, cli.output, cli.config) else {
+        unreachable!("input, output and config are required");
+    };
+    let input_file = std::env::current_dir().unwrap().to_path_buf().join(input);
+    let output_file = std::env::current_dir().unwrap().to_path_buf().join(output);
+    let config_file = std::env::current_dir().unwrap().to_path_buf().join(config);
     let temp_dir = if cli.working_dir.is_none() { Some(TempDir::new("sql_parser").expect("cannot create temporary dir")) } else { None };
-    let config = Config::from_file(config_file.as_path());
+    let mut config = Config::from_file(config_file.as_path());
+    parameters::init(config.parameters.as_ref(), &cli.set)?;
+    if let Some(rule_sets) = &config.rule_sets {
+        resolve_rule_sets(&mut config.cascades, rule_sets)?;
+        resolve_rule_sets(&mut config.filters, rule_sets)?;
+    }
+    let join_tables = config.join_tables.unwrap_or_default();
 
     let working_dir_path = match temp_dir {
         Some(ref dir) => dir.path().to_path_buf(),
         None => cli.working_dir.unwrap(),
     };
     let working_file_path = working_dir_path.join("INTERIM").with_extension("sql");
+    let memory_budget = cli.memory_budget.map(|megabytes| megabytes * 1024 * 1024);
+    let fold_case = config.case_insensitive_keys.unwrap_or(false);
+
+    explode_to_files(
+        working_file_path.as_path(),
+        input_file.as_path(),
+        |statement| {
+            if let (Some(allowed), Some(table)) = (&config.allow_data_on_tables, statement.get_table()) && !allowed.contains(table) {
+                return Ok(None);
+            }
+            Ok(Some(statement))
+        }
+    ).unwrap_or_else(|e| {
+        panic!("Problem exploding to files: {e:?}");
+    });
+
+    let has_patterns = config.cascades.keys().chain(config.filters.keys()).chain(config.text_transforms.keys()).any(|key| is_table_pattern(key));
+    if has_patterns {
+        let table_columns = get_table_columns(&working_file_path)?;
+        config.cascades = expand_table_patterns(config.cascades, &table_columns, |conditions| condition_columns(conditions))?;
+        config.filters = expand_table_patterns(config.filters, &table_columns, |conditions| condition_columns(conditions))?;
+        config.text_transforms = expand_table_patterns(config.text_transforms, &table_columns, |transforms| Ok(transforms.keys().cloned().collect()))?;
+    }
+
+    let mut pseudonymization_key: Option<Vec<u8>> = None;
+    for transform in config.text_transforms.values_mut().flat_map(|transforms| transforms.values_mut()) {
+        prepare_transform(transform, config.pseudonymization_key.as_ref(), &mut pseudonymization_key)?;
+    }
+    if !config.text_transforms.is_empty() {
+        check_transforms(&config.text_transforms, &get_table_data_types(&working_file_path)?)?;
+    }
+    let tokenizes = config.text_transforms.values().flat_map(|transforms| transforms.values()).any(|transform| transform.tokenizes());
+    let vault = match &config.vault {
+        Some(vault) => Some((vault.path(&output_file), vault.read_key()?)),
+        None if tokenizes => return Err(anyhow::anyhow!("tokenization needs a vault")),
+        None => None,
+    };
+
+    let new_store = || LookupStore::new(memory_budget, &working_dir_path, fold_case);
+    let mut lookup_table = match &config.seed {
+        Some(seed) => walk_seed(seed, &config.cascades, &join_tables, &working_file_path, new_store)?,
+        None => new_store(),
+    };
+    if let Some(dir) = &cli.import_keys {
+        lookup_table.import(dir)?;
+    }
+    for (name, key_file) in config.key_files.iter().flatten() {
+        let path = PathBuf::from(parameters::substitute(&key_file.path.to_string_lossy())?);
+        lookup_table.load_keys(&(String::from(FILE_KEY_PREFIX) + name), &path, key_file.column.as_deref())?;
+    }
+    if let Some((path, key)) = &vault {
+        load_vault(path, key, &mut lookup_table)?;
+    }
 
-    // explode_to_files(
-    //     working_file_path.as_path(),
-    //     input_file.as_path(),
-    //     |statement| {
-    //         if let (Some(allowed), Some(table)) = (&config.allow_data_on_tables, statement.get_table()) && !allowed.contains(table) {
-    //             return Ok(None);
-    //         }
-    //         Ok(Some(statement))
-    //     }
-    // ).unwrap_or_else(|e| {
-    //     panic!("Problem exploding to files: {e:?}");
-    // });
-
-    let mut lookup_table = HashMap::new();
-    for pending_tables in get_passes(config.cascades.iter().chain(&config.filters), config.text_transforms)? {
-        dbg!(&lookup_table);
+    let renumbering = match &config.renumber {
+        Some(renumber) => Some(Renumbering::new(
+            renumber,
+            config.cascades.iter().chain(&config.filters),
+            &join_tables,
+            &get_table_foreign_keys(&working_file_path)?,
+        )?),
+        None => None,
+    };
+
+    // in seed mode the cascades have already been walked, only plain filters are left
+    let (cascades, join_tables) = if config.seed.is_some() { (HashMap::new(), HashMap::new()) } else { (config.cascades, join_tables) };
+
+    let db_checks = get_passes(
+        cascades.iter().chain(&config.filters),
+        &join_tables,
+        config.text_transforms,
+        config.strict.unwrap_or(false),
+    )?;
+    for (table, table_checks) in db_checks.pre_pass.iter() {
+        scan_table_inserts(&working_file_path, table, |statement| table_checks.apply(statement, &mut lookup_table))?;
+    }
+    for pending_tables in db_checks.passes {
         for (table, table_checks) in pending_tables {
+            table_checks.start_tracking(&mut lookup_table);
             process_table_inserts(
                 &working_file_path,
                 &table,
@@ -82,6 +339,24 @@ fn main() -> Result<(), anyhow::Error> {
         }
     }
 
+    if let Some(dir) = &cli.export_keys {
+        lookup_table.export(dir)?;
+    }
+    if let Some((path, key)) = &vault {
+        save_vault(path, key, &lookup_table)?;
+    }
+
+    // ids are renumbered once every row is known, the exported keys keep the original ones
+    if let Some(renumbering) = &renumbering {
+        for table in renumbering.tables() {
+            process_table_inserts(&working_file_path, table, |statement| renumbering.number(table, statement, &mut lookup_table))?;
+        }
+        for table in renumbering.referencing_tables() {
+            process_table_inserts(&working_file_path, table, |statement| renumbering.propagate(table, statement, &lookup_table))?;
+        }
+        set_auto_increments(&working_file_path, &renumbering.next_ids(&lookup_table))?;
+    }
+
     gather(&working_file_path, &output_file)?;
 
     if let Some(dir) = temp_dir {
@@ -0,0 +1,111 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+lazy_static! {
+    static ref PLACEHOLDER_RE: Regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+}
+
+// CEL expressions see the parameters as fields of this variable, so that they never shadow a column
+pub const NAMESPACE: &str = "params";
+
+// the type of a parameter is the one of its declared default
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Parameter {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl Parameter {
+    fn with_value(&self, name: &str, value: &str) -> Result<Self, anyhow::Error> {
+        let parsed = match self {
+            Parameter::Bool(_) => value.parse().ok().map(Parameter::Bool),
+            Parameter::Int(_) => value.parse().ok().map(Parameter::Int),
+            Parameter::Float(_) => value.parse().ok().map(Parameter::Float),
+            Parameter::Text(_) => Some(Parameter::Text(value.to_owned())),
+        };
+        parsed.ok_or(anyhow::anyhow!("parameter {name} is declared as {}, cannot set it to {value}", self.type_name()))
+    }
+
+    fn type_name(&self) -> &str {
+        match self {
+            Parameter::Bool(_) => "a boolean",
+            Parameter::Int(_) => "an integer",
+            Parameter::Float(_) => "a number",
+            Parameter::Text(_) => "a string",
+        }
+    }
+}
+
+impl std::fmt::Display for Parameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Parameter::Bool(value) => value.fmt(f),
+            Parameter::Int(value) => value.fmt(f),
+            Parameter::Float(value) => value.fmt(f),
+            Parameter::Text(value) => value.fmt(f),
+        }
+    }
+}
+
+// set once on startup, checks are built through constructors that cannot carry them along
+static PARAMETERS: OnceLock<HashMap<String, Parameter>> = OnceLock::new();
+
+// declared parameters with their defaults, overridden by `name=value` pairs from the command line
+pub fn init(defaults: Option<&HashMap<String, Parameter>>, overrides: &[String]) -> Result<(), anyhow::Error> {
+    let mut parameters = defaults.cloned().unwrap_or_default();
+    for assignment in overrides.iter() {
+        let Some((name, value)) = assignment.split_once('=') else {
+            return Err(anyhow::anyhow!("cannot parse parameter {assignment}, expected name=value"));
+        };
+        let name = name.trim();
+        let Some(parameter) = parameters.get_mut(name) else {
+            return Err(anyhow::anyhow!("unknown parameter {name}, declare it under parameters in the config"));
+        };
+        *parameter = parameter.with_value(name, value)?;
+    }
+
+    // values may be secrets, only the names are printed
+    let mut names: Vec<&String> = parameters.keys().collect();
+    names.sort();
+    for name in names {
+        println!("Parameter {name}");
+    }
+    PARAMETERS.set(parameters).map_err(|_| anyhow::anyhow!("parameters are already set"))
+}
+
+pub fn get_all() -> &'static HashMap<String, Parameter> {
+    PARAMETERS.get_or_init(HashMap::new)
+}
+
+// replaces `${name}` placeholders with the value of the parameter
+pub fn substitute(text: &str) -> Result<String, anyhow::Error> {
+    let parameters = get_all();
+    if let Some(captures) = PLACEHOLDER_RE.captures_iter(text).find(|captures| !parameters.contains_key(&captures[1])) {
+        return Err(anyhow::anyhow!("unknown parameter {} in {text}", &captures[1]));
+    }
+    Ok(PLACEHOLDER_RE.replace_all(text, |captures: &regex::Captures| parameters[&captures[1]].to_string()).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_keep_the_declared_type() {
+        let text = Parameter::Text(String::from("1"));
+        assert_eq!(text.with_value("code", "007").unwrap(), Parameter::Text(String::from("007")));
+        assert_eq!(Parameter::Int(1).with_value("n", "-7").unwrap(), Parameter::Int(-7));
+        assert_eq!(Parameter::Bool(false).with_value("flag", "true").unwrap(), Parameter::Bool(true));
+        assert_eq!(Parameter::Float(0.5).with_value("ratio", "2").unwrap(), Parameter::Float(2.0));
+        assert!(Parameter::Int(1).with_value("n", "007x").is_err());
+        assert!(Parameter::Bool(false).with_value("flag", "1").is_err());
+        assert_eq!(Parameter::Text(String::from("007")).to_string(), "007");
+    }
+}
@@ -5,13 +5,13 @@ use lazy_static::lazy_static;
 use regex::Regex;
 use core::panic;
 use std::cell::RefCell;
-use std::{collections::HashMap, fs::File};
+use std::{collections::{HashMap, HashSet}, fs::File};
 use std::fs;
 use std::io::{self, BufRead, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-use crate::scanner::sql_parser::{TableColumnPositions, TableDataTypes, get_column_positions, get_data_types, split_insert_parts, is_create_table, is_insert, values};
+use crate::scanner::sql_parser::{TableColumnPositions, TableDataTypes, TableForeignKeys, get_column_positions, get_data_types, get_foreign_keys, split_insert_parts, is_create_table, is_insert, values};
 use crate::scanner::writers::{Writers, get_table_file};
 
 type DBMetaCell = Rc<RefCell<DBMeta>>;
@@ -39,6 +39,8 @@ impl<T: AbstractTransformFn<SqlStatement>> TransformFn for T {}
 
 lazy_static! {
     static ref TABLE_DUMP_RE: Regex = Regex::new(r"-- Dumping data for table `([^`]*)`").unwrap();
+    static ref CREATE_TABLE_RE: Regex = Regex::new(r"CREATE TABLE `([^`]*)`").unwrap();
+    static ref AUTO_INCREMENT_RE: Regex = Regex::new(r"AUTO_INCREMENT=\d+").unwrap();
 }
 
 #[derive(Clone)]
@@ -129,6 +131,7 @@ impl<'a> Extend<(&'a String, &'a String)> for SqlStatement {
 pub struct DBMeta {
     data_types: HashMap<String, Rc<TableDataTypes>>,
     column_positions: HashMap<String, Rc<TableColumnPositions>>,
+    foreign_keys: HashMap<String, TableForeignKeys>,
 }
 
 impl DBMeta {
@@ -144,19 +147,32 @@ impl DBMeta {
         Ok(Rc::new(RefCell::new(DBMeta {
             data_types: HashMap::new(),
             column_positions: HashMap::new(),
+            foreign_keys: HashMap::new(),
         })))
     }
 
+    fn table_data_types(&self) -> HashMap<String, TableDataTypes> {
+        self.data_types.iter().map(|(table, data_types)| (table.to_owned(), (**data_types).to_owned())).collect()
+    }
+
+    fn table_columns(&self) -> HashMap<String, HashSet<String>> {
+        self.data_types.iter().map(|(table, data_types)| (table.to_owned(), data_types.keys().cloned().collect())).collect()
+    }
+
     fn capture(&mut self, statement: &SqlStatement) -> EmptyResult {
-        if is_create_table(&statement.text) {
-            if let Some((table, data_types)) = get_data_types(&statement.text)? {
-                self.data_types.insert(table.to_string(), Rc::new(data_types));
+        if is_create_table(&statement.text)
+            && let Some((table, data_types)) = get_data_types(&statement.text)?
+        {
+            self.data_types.insert(table.to_string(), Rc::new(data_types));
+            if let Some((table, foreign_keys)) = get_foreign_keys(&statement.text)? {
+                self.foreign_keys.insert(table, foreign_keys);
             }
         }
-        if let Some(ref table) = statement.table {
-            if !self.column_positions.contains_key(table) && is_insert(&statement.text) {
-                self.column_positions.insert(table.to_string(), Rc::new(get_column_positions(&statement.text)?));
-            };
+        if let Some(ref table) = statement.table
+            && !self.column_positions.contains_key(table)
+            && is_insert(&statement.text)
+        {
+            self.column_positions.insert(table.to_string(), Rc::new(get_column_positions(&statement.text)?));
         }
         Ok(())
     }
@@ -265,10 +281,10 @@ impl Iterator for TrackedStatements {
     fn next(&mut self) -> Option<IteratorItem> {
         let mut statement = self.read_statement()?;
 
-        if let Ok(st) = &mut statement {
-            if let Err(e) = self.db_meta.borrow_mut().capture(st) {
-                return Some(Err(e));
-            }
+        if let Ok(st) = &mut statement
+            && let Err(e) = self.db_meta.borrow_mut().capture(st)
+        {
+            return Some(Err(e));
         }
 
         Some(statement)
@@ -291,8 +307,7 @@ impl<F: TransformFn> TransformedStatements<F> {
     fn transform_iteration_item(&mut self, statement_result: SqlStatementResult) -> Option<SqlStatementResult> {
         let Ok(mut statement) = statement_result else { return Some(statement_result); };
         statement.set_meta(&self.iter.db_meta);
-        let tr: Option<SqlStatement> = (self.transform)(statement).expect("err");
-        tr.map(Ok)
+        (self.transform)(statement).transpose()
     }
 }
 
@@ -341,12 +356,71 @@ pub fn process_table_inserts<F>(
 ) -> Result<(), anyhow::Error>
   where F: TransformFn
 {
-    println!("Processing records of table {table}");
     let input_filepath = &get_table_file(working_file_path, table)?;
+    if !input_filepath.exists() {
+        println!("No records of table {table}, skipping");
+        return Ok(());
+    }
+    println!("Processing records of table {table}");
 
     process(working_file_path, input_filepath, transform, Some(DBMeta::from_file(working_file_path)?))
 }
 
+// runs the transform over the records of a table without rewriting its file
+pub fn scan_table_inserts<F>(
+    working_file_path: &Path,
+    table: &str,
+    mut transform: F,
+) -> Result<(), anyhow::Error>
+  where F: TransformFn
+{
+    let input_filepath = &get_table_file(working_file_path, table)?;
+    if !input_filepath.exists() {
+        println!("No records of table {table}, skipping");
+        return Ok(());
+    }
+    println!("Scanning records of table {table}");
+
+    let db_meta = DBMeta::from_file(working_file_path)?;
+    for st in TransformedStatements::from_file(input_filepath, |statement| transform(statement).map(|_| None), Some(&db_meta))? {
+        st?;
+    }
+    Ok(())
+}
+
+// the columns of every table created in the dump
+pub fn get_table_columns(working_file_path: &Path) -> Result<HashMap<String, HashSet<String>>, anyhow::Error> {
+    Ok(DBMeta::from_file(working_file_path)?.borrow().table_columns())
+}
+
+// the column types of every table created in the dump
+pub fn get_table_data_types(working_file_path: &Path) -> Result<HashMap<String, TableDataTypes>, anyhow::Error> {
+    Ok(DBMeta::from_file(working_file_path)?.borrow().table_data_types())
+}
+
+// the foreign keys every table created in the dump declares
+pub fn get_table_foreign_keys(working_file_path: &Path) -> Result<HashMap<String, TableForeignKeys>, anyhow::Error> {
+    Ok(DBMeta::from_file(working_file_path)?.borrow().foreign_keys.to_owned())
+}
+
+// rewrites the AUTO_INCREMENT table option of the given tables
+pub fn set_auto_increments(working_file_path: &Path, next_ids: &HashMap<String, i64>) -> EmptyResult {
+    process(
+        working_file_path,
+        working_file_path,
+        |mut statement: SqlStatement| {
+            if is_create_table(&statement.text)
+                && let Some(captures) = CREATE_TABLE_RE.captures(&statement.text)
+                && let Some(next_id) = next_ids.get(&captures[1])
+            {
+                statement.text = AUTO_INCREMENT_RE.replace(&statement.text, format!("AUTO_INCREMENT={next_id}")).into_owned();
+            }
+            Ok(Some(statement))
+        },
+        None,
+    )
+}
+
 #[allow(dead_code)]
 pub fn gather(working_file_path: &Path, output_path: &Path) -> EmptyResult {
     let output = File::create(output_path)?;
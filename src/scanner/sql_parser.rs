@@ -11,6 +11,8 @@ use std::collections::HashMap;
 
 pub type TableDataTypes = HashMap<String, sqlparser::ast::DataType>;
 pub type TableColumnPositions = HashMap<String, usize>;
+// single column foreign keys as column and referenced `table.column`
+pub type TableForeignKeys = Vec<(String, String)>;
 
 fn quoted(i: &str) -> IResult<&str, &str> {
     recognize(delimited(
@@ -94,6 +96,27 @@ pub fn get_data_types(create_statement: &str) -> Result<Option<(String, TableDat
     Ok(None)
 }
 
+pub fn get_foreign_keys(create_statement: &str) -> Result<Option<(String, TableForeignKeys)>, anyhow::Error> {
+    let dialect = MySqlDialect {};
+    let ast = SqlParser::parse_sql(&dialect, create_statement)?;
+    for st in ast.into_iter() {
+        if let sqlparser::ast::Statement::CreateTable(ct) = st {
+            let table = ct.name.0[0].as_ident().unwrap().value.to_string();
+            let foreign_keys = ct.constraints.iter().filter_map(|constraint| match constraint {
+                sqlparser::ast::TableConstraint::ForeignKey { columns, foreign_table, referred_columns, .. }
+                    if columns.len() == 1 && referred_columns.len() == 1 =>
+                {
+                    let foreign_table = foreign_table.0.last()?.as_ident()?.value.to_string();
+                    Some((columns[0].value.to_string(), foreign_table + "." + &referred_columns[0].value))
+                },
+                _ => None,
+            }).collect();
+            return Ok(Some((table, foreign_keys)));
+        }
+    }
+    Ok(None)
+}
+
 pub fn get_column_positions(insert_statement: &str) -> Result<HashMap<String, usize>, anyhow::Error> {
     let dialect = MySqlDialect {};
     let ast = SqlParser::parse_sql(&dialect, insert_statement)?;
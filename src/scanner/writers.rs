@@ -13,6 +13,7 @@ pub fn get_table_file(working_file_path: &Path, table: &str) -> Result<PathBuf,
 
 #[derive(Debug)]
 struct Writer {
+    #[allow(dead_code)]
     table: Option<String>,
     filepath: PathBuf,
     tmp_filepath: PathBuf,
@@ -83,12 +84,13 @@ impl Writers {
     }
 
     pub fn write_statement(&mut self, table_option: &Option<String>, statement: &[u8]) -> EmptyResult {
-        if let Some(table) = table_option {
-            if self.writer_per_table.contains_key(&None) && !self.writer_per_table.contains_key(table_option) {
-                let filepath = self.get_table_file(table)?;
-                let working_file_writer = self.get_writer(&None)?;
-                working_file_writer.write_statement(format!("--- INLINE {} {}\n", filepath.display(), table).as_bytes())?;
-            }
+        if let Some(table) = table_option
+            && self.writer_per_table.contains_key(&None)
+            && !self.writer_per_table.contains_key(table_option)
+        {
+            let filepath = self.get_table_file(table)?;
+            let working_file_writer = self.get_writer(&None)?;
+            working_file_writer.write_statement(format!("--- INLINE {} {}\n", filepath.display(), table).as_bytes())?;
         }
         let writer = self.get_writer(table_option)?;
         writer.write_statement(statement)?;